@@ -0,0 +1,186 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// Commands a package provides, linked into `node_modules/.bin` so scripts
+// can run them by name
+
+// Command name -> path of the script inside the package, from the `bin`
+// field of a package.json. A string is a single command named after the
+// package (without its scope). Entries that could escape `.bin` or the
+// package directory are dropped.
+pub fn bin_entries(package_json: &Value) -> BTreeMap<String, PathBuf> {
+    let mut entries = BTreeMap::new();
+    match package_json.get("bin") {
+        Some(Value::String(path)) => {
+            let name = package_json.get("name").and_then(Value::as_str).unwrap_or_default();
+            let command = name.rsplit('/').next().unwrap_or_default();
+            insert_entry(&mut entries, command, path);
+        }
+        Some(Value::Object(map)) => {
+            for (command, path) in map {
+                if let Some(path) = path.as_str() {
+                    insert_entry(&mut entries, command, path);
+                }
+            }
+        }
+        _ => {}
+    }
+    entries
+}
+
+fn insert_entry(entries: &mut BTreeMap<String, PathBuf>, command: &str, path: &str) {
+    // `@scope/tool` as a command name means `tool`
+    let command = command.rsplit('/').next().unwrap_or_default();
+    if command.is_empty() || command.starts_with('.') || command.contains('\\') {
+        return;
+    }
+    if let Some(path) = package_relative(path) {
+        entries.insert(command.to_string(), path);
+    }
+}
+
+// A relative path that stays inside the package
+fn package_relative(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(&path.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+// Commands of an installed package. Without a `bin` field, every file in
+// `directories.bin` is a command.
+pub fn read_bins(package_dir: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let content = match fs::read_to_string(package_dir.join("package.json")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let package_json: Value = serde_json::from_str(&content)?;
+
+    let mut entries = bin_entries(&package_json);
+    if entries.is_empty() {
+        let dir = package_json.pointer("/directories/bin").and_then(Value::as_str).and_then(package_relative);
+        if let Some(dir) = dir {
+            collect_files(package_dir, &dir, &mut entries)?;
+        }
+    }
+    Ok(entries)
+}
+
+fn collect_files(package_dir: &Path, dir: &Path, entries: &mut BTreeMap<String, PathBuf>) -> Result<()> {
+    let Ok(read) = fs::read_dir(package_dir.join(dir)) else { return Ok(()) };
+    for entry in read.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            collect_files(package_dir, &dir.join(&name), entries)?;
+        } else if !name.starts_with('.') {
+            entries.insert(name.clone(), dir.join(&name));
+        }
+    }
+    Ok(())
+}
+
+// Link the commands of `node_modules/<package>` into `node_modules/.bin`.
+// Returns the command names.
+pub fn link_bins(node_modules: &Path, package: &str) -> Result<Vec<String>> {
+    let package_dir = node_modules.join(package);
+    let entries = read_bins(&package_dir)?;
+    if entries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let bin_dir = node_modules.join(".bin");
+    fs::create_dir_all(&bin_dir)?;
+    for (command, path) in &entries {
+        let script = package_dir.join(path);
+        if !script.is_file() {
+            continue;
+        }
+        make_executable(&script)?;
+        // Relative, so the project can be moved
+        let target = Path::new("..").join(package).join(path);
+        write_link(&bin_dir, command, &target)?;
+    }
+    Ok(entries.into_keys().collect())
+}
+
+// Remove the commands pointing into `node_modules/<package>`
+pub fn unlink_bins(node_modules: &Path, package: &str) -> Result<()> {
+    let package_dir = Path::new("..").join(package);
+    remove_links(&node_modules.join(".bin"), |target| target.starts_with(&package_dir))
+}
+
+// Remove commands whose package is gone
+pub fn prune_bins(node_modules: &Path) -> Result<()> {
+    let bin_dir = node_modules.join(".bin");
+    remove_links(&bin_dir, |target| !bin_dir.join(target).exists())
+}
+
+fn remove_links(bin_dir: &Path, remove: impl Fn(&Path) -> bool) -> Result<()> {
+    let Ok(entries) = fs::read_dir(bin_dir) else { return Ok(()) };
+    for entry in entries.flatten() {
+        if let Some(target) = link_target(&entry.path()) {
+            if remove(&target) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_link(bin_dir: &Path, command: &str, target: &Path) -> io::Result<()> {
+    let link = bin_dir.join(command);
+    if fs::read_link(&link).map(|current| current == target).unwrap_or(false) {
+        return Ok(());
+    }
+    if fs::symlink_metadata(&link).is_ok() {
+        fs::remove_file(&link)?;
+    }
+    std::os::unix::fs::symlink(target, &link)
+}
+
+#[cfg(unix)]
+fn link_target(link: &Path) -> Option<PathBuf> {
+    fs::read_link(link).ok()
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o111 != 0o111 {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode | 0o111))?;
+    }
+    Ok(())
+}
+
+// Windows can't execute scripts through symlinks, so a .cmd shim runs the
+// script with node instead
+#[cfg(windows)]
+fn write_link(bin_dir: &Path, command: &str, target: &Path) -> io::Result<()> {
+    let shim = format!("@node \"%~dp0\\{}\" %*\r\n", target.display().to_string().replace('/', "\\"));
+    fs::write(bin_dir.join(format!("{}.cmd", command)), shim)
+}
+
+#[cfg(windows)]
+fn link_target(shim: &Path) -> Option<PathBuf> {
+    let content = fs::read_to_string(shim).ok()?;
+    let start = content.find("%~dp0\\")? + "%~dp0\\".len();
+    let end = start + content[start..].find('"')?;
+    Some(PathBuf::from(&content[start..end]))
+}
+
+#[cfg(windows)]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -1,13 +1,26 @@
 use futures::stream::{self, StreamExt};
-use std::path::PathBuf;
-use dirs::home_dir;
+use std::path::{Path, PathBuf};
+use crate::integrity::Integrity;
+use crate::lockfile::LockedPackage;
 use colored::*;
 use std::fs;
 use anyhow::Result;
 
+// Returned when offline mode needs something the cache doesn't have
+#[derive(Debug)]
+pub struct NotCached(pub String);
+
+impl std::fmt::Display for NotCached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not in the cache and offline mode is enabled", self.0)
+    }
+}
+
+impl std::error::Error for NotCached {}
+
 // Get package cache directory
 pub fn get_cache_dir() -> PathBuf {
-    home_dir().unwrap_or_default().join(".a-pm-cache")
+    crate::config::get().cache_dir.clone()
 }
 
 // Create cache folders if they don't exist
@@ -19,25 +32,61 @@ pub fn ensure_cache_dirs() -> Result<()> {
     Ok(())
 }
 
+// Location of a package's tarball in the cache
+pub fn tarball_path(pkg: &str, version: &str) -> PathBuf {
+    get_cache_dir()
+        .join("packages")
+        .join(format!("{}-{}.tgz", pkg.replace("/", "-"), version))
+}
+
 // Check if package is cached
 pub fn is_package_cached(pkg: &str, version: &str) -> bool {
-    let pkg_path = get_cache_dir()
-        .join("packages")
-        .join(format!("{}-{}.tgz", pkg.replace("/", "-"), version));
-    pkg_path.exists()
+    tarball_path(pkg, version).exists()
+}
+
+// Read a cached tarball, evicting it if it fails the integrity check so the
+// caller downloads a fresh copy
+pub fn read_cached_tarball(path: &Path, expected: Option<&Integrity>) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+
+    if let Some(expected) = expected {
+        if let Err(e) = expected.check(&bytes) {
+            println!("{}", format!("⚠️ Cached {} is corrupt ({}), re-fetching", 
+                     path.display(), e).yellow());
+            let _ = fs::remove_file(path);
+            return None;
+        }
+    }
+
+    Some(bytes)
 }
 
-// Perform concurrent installations
+// Store a tarball in the cache. Written to a temporary file first so an
+// interrupted download never leaves a truncated entry behind.
+pub fn write_cached_tarball(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension(format!("tgz.{}.tmp", std::process::id()));
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Perform concurrent installations of (install path, locked package) pairs
 pub async fn install_packages_concurrently(
-    packages: Vec<(String, String)>,
+    packages: Vec<(String, LockedPackage)>,
     max_concurrent: usize,
 ) -> Result<()> {
     println!("{}", format!("🚀 Installing {} packages...", packages.len()).blue());
 
     let results = stream::iter(packages)
-        .map(|(pkg, ver)| async move {
-            let result = crate::registry::download_and_unpack(&pkg, &ver).await;
-            (pkg, ver, result)
+        .map(|(path, locked)| async move {
+            let pkg = crate::lockfile::package_name(&path).to_string();
+            let ver = locked.version.clone();
+            let dist = locked.dist();
+            let result = crate::registry::install_package(&pkg, &ver, dist.as_ref(), Path::new(&path)).await;
+            (pkg, ver, locked.optional, result)
         })
         .buffer_unordered(max_concurrent)
         .collect::<Vec<_>>()
@@ -46,12 +95,16 @@ pub async fn install_packages_concurrently(
     let mut success_count = 0;
     let mut failure_count = 0;
 
-    for (pkg, ver, result) in results {
+    for (pkg, ver, optional, result) in results {
         match result {
             Ok(_) => {
                 success_count += 1;
                 println!("{}", format!("✅ Installed {}@{}", pkg, ver).green());
             }
+            // Optional packages are allowed to fail, like npm does
+            Err(e) if optional => {
+                println!("{}", format!("⚠️ Skipped optional {}@{}: {}", pkg, ver, e).yellow());
+            }
             Err(e) => {
                 failure_count += 1;
                 println!("{}", format!("❌ Failed to install {}@{}: {}", pkg, ver, e).red());
@@ -1,55 +1,272 @@
 use crate::registry;
+use crate::layout;
 use crate::lockfile;
 use crate::manifest;
+use crate::platform::Platform;
+use crate::resolver;
+use crate::scripts;
+use crate::store;
+use crate::config::Linker;
 use colored::*;
+use std::collections::{BTreeSet, HashSet};
 use std::fs;
-use std::process::Command;
+use std::path::Path;
 
-pub async fn add(package: &str, dev: bool) -> anyhow::Result<()> {
+pub async fn add(package: &str, dev: bool, ignore_scripts: bool) -> anyhow::Result<()> {
     println!("{}", format!("🔍 Resolving {} ...", package).blue());
     let version = registry::get_latest_version(package).await?;
     println!("{}", format!("📦 Adding {}@{}", package, version).green());
 
-    // Update manifest
-    manifest::add_dependency(package, &version, dev)?;
+    // Like npm, the manifest allows compatible updates of the added version
+    manifest::add_dependency(package, &format!("^{}", version), dev)?;
     
     // Update lockfile
     lockfile::add_dependency(package, &version)?;
     
-    // Immediately install the package
-    registry::download_and_unpack(package, &version).await?;
+    // Install the package together with its own dependencies
+    install(false, ignore_scripts).await?;
     
     println!("{}", format!("✅ Added {}@{} to {}", package, version, 
              if dev { "devDependencies" } else { "dependencies" }).green());
     Ok(())
 }
 
-pub async fn install() -> anyhow::Result<()> {
-    println!("{}", "🔑 Reading lockfile...".blue());
-    let deps = lockfile::read_dependencies()?;
+pub async fn install(frozen_lockfile: bool, ignore_scripts: bool) -> anyhow::Result<()> {
+    let manifest = manifest::read_manifest()?;
     
-    if deps.is_empty() {
+    if frozen_lockfile {
+        return install_locked(&manifest, false, ignore_scripts).await;
+    }
+    
+    // Production and dev dependencies are both installed locally
+    let mut deps = manifest.dependencies.clone();
+    deps.extend(manifest.dev_dependencies.clone());
+    
+    if deps.is_empty() && manifest.optional_dependencies.is_empty() {
         println!("{}", "No dependencies to install.".yellow());
+        // Whatever was locked before is no longer needed
+        lockfile::write_lockfile(&lockfile::Lockfile::default())?;
+        let project_dir = std::env::current_dir()?;
+        scripts::run_root_scripts(&project_dir, scripts::ROOT_STAGES_BEFORE_INSTALL, ignore_scripts).await?;
+        scripts::run_root_scripts(&project_dir, scripts::ROOT_STAGES_AFTER_INSTALL, ignore_scripts).await?;
         return Ok(());
     }
     
-    // Convert the dependencies to a format suitable for parallel installation
-    let packages: Vec<(String, String)> = deps.into_iter().collect();
+    // A lockfile that still matches the manifest is installed as is, without
+    // asking the registry (or the offline cache) again
+    println!("{}", "🔑 Reading lockfile...".blue());
+    let lock = lockfile::read_lockfile()?;
+    if lock.verify(&deps, &manifest.optional_dependencies).is_empty() {
+        // Written back to drop any merged conflict markers
+        lockfile::write_lockfile(&lock)?;
+        return install_lockfile(lock, &manifest, ignore_scripts).await;
+    }
+    
+    // Previously locked versions are preferred whenever they still satisfy the ranges
+    let locked = lockfile::read_dependencies()?;
+    
+    println!("{}", "🔍 Resolving dependency tree...".blue());
+    let graph = resolver::resolve_dependencies(&deps, &manifest.optional_dependencies, &locked).await?;
+    
+    // Hoist what can be shared and nest conflicting versions
+    let placement = layout::plan(&graph);
+    let nested = placement.keys().filter(|path| path.matches("node_modules/").count() > 1).count();
+    if nested > 0 {
+        println!("{}", format!("📐 Nesting {} packages to avoid version conflicts", nested).blue());
+    }
+    
+    // Peers are checked against the copy each package finds where it is installed
+    report_peer_conflicts(&resolver::peer_conflicts(&graph, &placement));
+    
+    let prod_roots: HashSet<String> = manifest.dependencies.keys()
+        .chain(manifest.optional_dependencies.keys())
+        .cloned()
+        .collect();
+    let lock = lockfile::Lockfile::from_graph(&graph, &placement, &prod_roots);
+    lockfile::write_lockfile(&lock)?;
+    
+    install_lockfile(lock, &manifest, ignore_scripts).await
+}
+
+// Explain which packages want which peer ranges, and what to change
+fn report_peer_conflicts(conflicts: &[resolver::PeerConflict]) {
+    if conflicts.is_empty() {
+        return;
+    }
+    
+    println!("{}", "⚠️ Peer dependency problems:".yellow());
+    for conflict in conflicts {
+        match &conflict.installed {
+            Some(version) => println!("   {}@{} is installed, but:", conflict.peer, version),
+            None => println!("   {} is not installed, but:", conflict.peer),
+        }
+        for (dependent, range) in &conflict.wanted_by {
+            println!("     - {} wants {}@{}", dependent, conflict.peer, range);
+        }
+        let action = if conflict.installed.is_some() { "Change" } else { "Add" };
+        println!("{}", format!("   → {} {} in {} to a version that satisfies these ranges", 
+                 action, conflict.peer, manifest::manifest_file()).yellow());
+    }
+}
+
+// `a ci`: a clean install of exactly what a.lock records
+pub async fn ci(ignore_scripts: bool) -> anyhow::Result<()> {
+    let manifest = manifest::read_manifest()?;
+    install_locked(&manifest, true, ignore_scripts).await
+}
+
+// Install a.lock as committed, failing instead of updating it when it
+// doesn't match the manifest
+async fn install_locked(manifest: &manifest::Manifest, clean: bool, ignore_scripts: bool) -> anyhow::Result<()> {
+    println!("{}", format!("🔒 Checking a.lock against {}...", manifest::manifest_file()).blue());
+    let lock = lockfile::read_frozen_lockfile()?;
+    
+    let mut deps = manifest.dependencies.clone();
+    deps.extend(manifest.dev_dependencies.clone());
+    
+    let problems = lock.verify(&deps, &manifest.optional_dependencies);
+    if !problems.is_empty() {
+        anyhow::bail!("a.lock is out of sync with {}:\n  - {}\nRun `a install` and commit the updated a.lock",
+                      manifest::manifest_file(), problems.join("\n  - "));
+    }
+    
+    if clean && fs::metadata("node_modules").is_ok() {
+        println!("{}", "🧹 Removing node_modules...".blue());
+        fs::remove_dir_all("node_modules")?;
+    }
+    
+    install_lockfile(lock, manifest, ignore_scripts).await
+}
+
+async fn install_lockfile(lock: lockfile::Lockfile, manifest: &manifest::Manifest, ignore_scripts: bool) -> anyhow::Result<()> {
+    let linker = crate::config::get().linker;
+    
+    // The lockfile lists the packages of every platform; only ours are installed
+    let platform = Platform::current();
+    let (packages, other_platforms): (Vec<(String, lockfile::LockedPackage)>, Vec<_>) = lock.packages.into_iter()
+        .partition(|(_, locked)| locked.supports(&platform));
+    
+    let unsupported: Vec<String> = other_platforms.iter()
+        .filter(|(_, locked)| !locked.optional)
+        .map(|(path, locked)| format!("{}@{}", lockfile::package_name(path), locked.version))
+        .collect();
+    if !unsupported.is_empty() {
+        anyhow::bail!("Required packages don't support this platform ({}): {}", platform, unsupported.join(", "));
+    }
+    if !other_platforms.is_empty() {
+        println!("{}", format!("⏭️ Skipping {} optional packages for other platforms", other_platforms.len()).blue());
+    }
+    
+    // Offline, report every missing tarball up front instead of failing one by one
+    if crate::config::get().network == crate::config::NetworkMode::Offline {
+        let missing: Vec<String> = packages.iter()
+            .filter(|(_, locked)| !locked.optional)
+            .filter(|(path, locked)| !crate::cache::is_package_cached(lockfile::package_name(path), &locked.version))
+            .filter(|(_, locked)| linker != Linker::Isolated || !store::is_stored(locked))
+            .map(|(path, locked)| format!("{}@{}", lockfile::package_name(path), locked.version))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("Offline mode is enabled and the cache has no tarball for: {}", missing.join(", "));
+        }
+    }
+    
+    // Defaults to a value based on the system, overridable with A_MAX_CONCURRENT
+    let parallel_count = crate::config::get().max_concurrent;
+    
+    // The project's preinstall runs before anything is installed
+    let project_dir = std::env::current_dir()?;
+    scripts::run_root_scripts(&project_dir, scripts::ROOT_STAGES_BEFORE_INSTALL, ignore_scripts).await?;
     
-    // Determine a reasonable number of parallel downloads based on system
-    let parallel_count = std::cmp::min(num_cpus::get() * 2, 8); // Reasonable default
+    let installed = lockfile::Lockfile { packages: packages.into_iter().collect(), ..Default::default() };
     
-    println!("{}", format!("🚀 Installing {} packages with {} parallel downloads", 
-             packages.len(), parallel_count).blue());
+    if linker == Linker::Isolated {
+        let roots: Vec<String> = manifest.dependencies.keys()
+            .chain(manifest.dev_dependencies.keys())
+            .chain(manifest.optional_dependencies.keys())
+            .cloned()
+            .collect();
+        store::install_isolated(&installed, &roots, parallel_count).await?;
+    } else {
+        println!("{}", format!("🚀 Installing {} packages with {} parallel downloads", 
+                 installed.packages.len(), parallel_count).blue());
+        
+        // Install packages in parallel
+        let packages = installed.packages.iter().map(|(path, locked)| (path.clone(), locked.clone())).collect();
+        crate::cache::install_packages_concurrently(packages, parallel_count).await?;
+        
+        // Each package's commands go in the .bin folder next to it
+        for path in installed.packages.keys() {
+            let name = lockfile::package_name(path);
+            let node_modules = &path[..path.len() - name.len() - 1];
+            crate::bin::link_bins(Path::new(node_modules), name)?;
+        }
+        crate::bin::prune_bins(Path::new("node_modules"))?;
+    }
     
-    // Install packages in parallel
-    crate::cache::install_packages_concurrently(packages, parallel_count).await?;
+    if ignore_scripts {
+        println!("{}", "⏭️ Skipping install scripts".blue());
+    } else {
+        run_dependency_scripts(&installed, linker, &manifest.trusted_dependencies).await?;
+    }
+    scripts::run_root_scripts(&project_dir, scripts::ROOT_STAGES_AFTER_INSTALL, ignore_scripts).await?;
     
     println!("{}", "✅ All dependencies installed!".green());
     Ok(())
 }
 
-pub async fn remove(package: &str, dev: bool) -> anyhow::Result<()> {
+// Run the install scripts of trusted dependencies, each after the packages
+// it depends on. Scripts of other packages are skipped and listed.
+async fn run_dependency_scripts(lock: &lockfile::Lockfile, linker: Linker, trusted: &[String]) -> anyhow::Result<()> {
+    let project_dir = std::env::current_dir()?;
+    let mut seen = HashSet::new();
+    let mut skipped = BTreeSet::new();
+    
+    for path in lock.dependency_order() {
+        let locked = &lock.packages[&path];
+        let name = lockfile::package_name(&path);
+        let id = resolver::node_key(name, &locked.version);
+        let dir = match linker {
+            // Every version is linked once, however often it is depended on
+            Linker::Isolated if !seen.insert(id.clone()) => continue,
+            Linker::Isolated => store::package_dir(&project_dir, name, &locked.version),
+            Linker::Hoisted => project_dir.join(&path),
+        };
+        
+        let package_json = scripts::read_package_json(&dir)?;
+        let stages = scripts::install_scripts(&dir, &package_json);
+        if stages.is_empty() {
+            continue;
+        }
+        if !trusted.iter().any(|t| t == name) {
+            skipped.insert(id);
+            continue;
+        }
+        
+        for (stage, script) in stages {
+            println!("{}", format!("⚙️ Running {} of {}", stage, id).blue());
+            let code = scripts::run(scripts::command(&dir, &package_json, &stage, &script)?).await?;
+            if code == 0 {
+                continue;
+            }
+            // Like a failed download, a failed build of an optional package is skipped
+            if locked.optional {
+                println!("{}", format!("⚠️ {} of optional {} failed with exit code {}", stage, id, code).yellow());
+                break;
+            }
+            return Err(scripts::ScriptFailed { name: format!("{} of {}", stage, id), code }.into());
+        }
+    }
+    
+    if !skipped.is_empty() {
+        println!("{}", format!("⚠️ Skipped install scripts of untrusted packages: {}",
+                 skipped.into_iter().collect::<Vec<_>>().join(", ")).yellow());
+        println!("{}", format!("   → Add them to trustedDependencies in {} to run them",
+                 manifest::manifest_file()).yellow());
+    }
+    Ok(())
+}
+
+pub async fn remove(package: &str, dev: bool, ignore_scripts: bool) -> anyhow::Result<()> {
     println!("{}", format!("❌ Removing {} ...", package).red());
     
     // Update manifest
@@ -58,12 +275,16 @@ pub async fn remove(package: &str, dev: bool) -> anyhow::Result<()> {
     // Update lockfile
     lockfile::remove_dependency(package)?;
     
-    // Remove from node_modules
+    // Remove from node_modules, commands first
+    crate::bin::unlink_bins(Path::new("node_modules"), package)?;
     let pkg_dir = format!("node_modules/{}", package);
-    if fs::metadata(&pkg_dir).is_ok() {
+    if fs::symlink_metadata(&pkg_dir).is_ok() {
         fs::remove_dir_all(pkg_dir)?;
     }
     
+    // Re-resolve so dependencies only it needed leave a.lock too
+    install(false, ignore_scripts).await?;
+    
     println!("{}", format!("✅ Removed {}", package).green());
     Ok(())
 }
@@ -93,77 +314,128 @@ pub async fn init(name: Option<&str>) -> anyhow::Result<()> {
     println!("{}", format!("📝 Creating a new package: {}", pkg_name).blue());
     manifest::init(&pkg_name)?;
     
-    println!("{}", "✅ Created a.json file".green());
+    println!("{}", format!("✅ Created {} file", manifest::manifest_file()).green());
     Ok(())
 }
 
-pub async fn run_script(script_name: &str) -> anyhow::Result<()> {
+pub async fn run_script(script_name: Option<&str>, args: &[String]) -> anyhow::Result<()> {
     let manifest = manifest::read_manifest()?;
     
-    if let Some(script) = manifest.scripts.get(script_name) {
-        println!("{}", format!("▶️ Running script: {}", script_name).blue());
-        
-        let output = if cfg!(target_os = "windows") {
-            Command::new("cmd")
-                .args(["/C", script])
-                .output()?
-        } else {
-            Command::new("sh")
-                .arg("-c")
-                .arg(script)
-                .output()?
-        };
-        
-        println!("{}", String::from_utf8_lossy(&output.stdout));
-        
-        if !output.status.success() {
-            println!("{}", format!("❌ Script '{}' failed with exit code: {:?}", 
-                     script_name, output.status.code()).red());
-            return Err(anyhow::anyhow!("Script execution failed"));
-        }
-        
-        println!("{}", format!("✅ Script '{}' completed successfully", script_name).green());
-    } else {
-        return Err(anyhow::anyhow!("Script '{}' not found in a.json", script_name));
+    let Some(script_name) = script_name else {
+        list_scripts(&manifest);
+        return Ok(());
+    };
+    let script = manifest.scripts.get(script_name)
+        .ok_or_else(|| anyhow::anyhow!("Script '{}' not found in {}", script_name, manifest::manifest_file()))?;
+    
+    // Local tools and npm's environment variables are available to the script
+    let project_dir = std::env::current_dir()?;
+    let package_json = scripts::read_package_json(&project_dir)?;
+    
+    // pre<name> and post<name> run around the script, without its arguments;
+    // the first one to fail stops the rest
+    let pre = format!("pre{}", script_name);
+    if let Some(hook) = manifest.scripts.get(&pre) {
+        scripts::run_script(&project_dir, &package_json, &pre, hook).await?;
+    }
+    scripts::run_script(&project_dir, &package_json, script_name, &scripts::with_args(script, args)).await?;
+    let post = format!("post{}", script_name);
+    if let Some(hook) = manifest.scripts.get(&post) {
+        scripts::run_script(&project_dir, &package_json, &post, hook).await?;
     }
     
+    println!("{}", format!("✅ Script '{}' completed successfully", script_name).green());
     Ok(())
 }
 
-pub async fn update(pkg: Option<&str>) -> anyhow::Result<()> {
-    if let Some(package) = pkg {
-        // Update specific package
-        println!("{}", format!("🔄 Updating {} ...", package).blue());
-        let version = registry::get_latest_version(package).await?;
-        
-        // Update lockfile
-        lockfile::add_dependency(package, &version)?;
-        
-        // Update the package
-        registry::download_and_unpack(package, &version).await?;
-        
-        println!("{}", format!("✅ Updated {}@{}", package, version).green());
-    } else {
-        // Update all packages
-        println!("{}", "🔄 Updating all dependencies...".blue());
-        
-        // Read from manifest
-        let manifest = manifest::read_manifest()?;
-        
-        for (pkg, _) in &manifest.dependencies {
-            let version = registry::get_latest_version(pkg).await?;
-            println!("{}", format!("� Updating {}@{}", pkg, version).yellow());
-            
-            // Update lockfile
-            lockfile::add_dependency(pkg, &version)?;
-            
-            // Update the package
-            registry::download_and_unpack(pkg, &version).await?;
+fn list_scripts(manifest: &manifest::Manifest) {
+    if manifest.scripts.is_empty() {
+        println!("No scripts in {}", manifest::manifest_file());
+        return;
+    }
+    
+    println!("{}", format!("📜 Scripts in {}:", manifest::manifest_file()).blue());
+    for (name, script) in &manifest.scripts {
+        println!("   {} {}", "➤".blue(), name);
+        println!("       {}", script);
+    }
+}
+
+// `a exec`: run a command from node_modules/.bin with the environment of
+// scripts
+pub async fn exec(bin: &str, args: &[String]) -> anyhow::Result<()> {
+    let project_dir = std::env::current_dir()?;
+    let path = crate::exec::find_bin(&project_dir, bin)
+        .ok_or_else(|| anyhow::anyhow!("'{}' is not installed in node_modules/.bin; try `a dlx {}`", bin, bin))?;
+    
+    let package_json = scripts::read_package_json(&project_dir)?;
+    let command = scripts::with_args(&scripts::quote(&path.to_string_lossy()), args);
+    let code = scripts::run(scripts::command(&project_dir, &package_json, "exec", &command)?).await?;
+    if code != 0 {
+        return Err(scripts::ScriptFailed { name: bin.to_string(), code }.into());
+    }
+    Ok(())
+}
+
+// `a dlx`: install a package into the cache and run its command, without
+// touching the project
+pub async fn dlx(spec: &str, args: &[String]) -> anyhow::Result<()> {
+    let (name, range) = crate::exec::parse_spec(spec);
+    let dir = crate::exec::install_temporary(&name, &range).await?;
+    
+    let bins = crate::bin::read_bins(&dir.join("node_modules").join(&name))?;
+    let bin = crate::exec::pick_bin(&name, &bins)?;
+    let path = crate::exec::find_bin(&dir, &bin)
+        .ok_or_else(|| anyhow::anyhow!("'{}' of {} could not be linked", bin, name))?;
+    
+    // The package's own dependencies come first on the PATH
+    let project_dir = std::env::current_dir()?;
+    let package_json = scripts::read_package_json(&project_dir)?;
+    let mut command = scripts::command(&project_dir, &package_json, "dlx",
+                                       &scripts::with_args(&scripts::quote(&path.to_string_lossy()), args))?;
+    let project_path = scripts::bin_path(&project_dir, std::env::var_os("PATH").as_ref())?;
+    command.env("PATH", std::env::join_paths(
+        std::iter::once(dir.join("node_modules").join(".bin")).chain(std::env::split_paths(&project_path)))?);
+    
+    println!("{}", format!("▶️ Running {} from {}", bin, name).blue());
+    let code = scripts::run(command).await?;
+    if code != 0 {
+        return Err(scripts::ScriptFailed { name: bin, code }.into());
+    }
+    Ok(())
+}
+
+pub async fn update(pkg: Option<&str>, ignore_scripts: bool) -> anyhow::Result<()> {
+    let manifest = manifest::read_manifest()?;
+    let packages: Vec<String> = match pkg {
+        Some(package) => {
+            let declared = manifest.dependencies.contains_key(package)
+                || manifest.dev_dependencies.contains_key(package)
+                || manifest.optional_dependencies.contains_key(package);
+            if !declared {
+                anyhow::bail!("{} is not a dependency in {}", package, manifest::manifest_file());
+            }
+            vec![package.to_string()]
         }
-        
-        println!("{}", "✅ All dependencies updated!".green());
+        None => manifest.dependencies.keys()
+            .chain(manifest.dev_dependencies.keys())
+            .chain(manifest.optional_dependencies.keys())
+            .cloned()
+            .collect(),
+    };
+    
+    println!("{}", "🔄 Updating dependencies...".blue());
+    for package in &packages {
+        let version = registry::get_latest_version(package).await?;
+        println!("{}", format!("🔄 Updating {} to {}", package, version).yellow());
+        manifest::update_dependency(package, &format!("^{}", version))?;
     }
     
+    // Resolve, lock and install the new versions with their dependencies;
+    // locked versions outside the new ranges are dropped
+    install(false, ignore_scripts).await?;
+    
+    println!("{}", "✅ All dependencies updated!".green());
     Ok(())
 }
 
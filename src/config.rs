@@ -0,0 +1,183 @@
+use anyhow::Result;
+use crate::npmrc::Npmrc;
+use dirs::home_dir;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+// How much the network may be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkMode {
+    #[default]
+    Online,
+    // Cached registry documents are used without revalidating them
+    PreferOffline,
+    // Everything must come from the cache
+    Offline,
+}
+
+// How packages are laid out in node_modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Linker {
+    // npm style: every package copied into a hoisted node_modules tree
+    #[default]
+    Hoisted,
+    // pnpm style: packages hardlinked from a global store, each seeing only
+    // its own dependencies
+    Isolated,
+}
+
+impl Linker {
+    fn parse(value: &str) -> Option<Linker> {
+        match value {
+            "hoisted" => Some(Linker::Hoisted),
+            "isolated" => Some(Linker::Isolated),
+            _ => None,
+        }
+    }
+}
+
+// Settings read once at startup and shared by the registry, cache and
+// install code
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Registry base URL, without a trailing slash
+    pub registry: String,
+    pub cache_dir: PathBuf,
+    // Upper bound for parallel downloads and registry requests
+    pub max_concurrent: usize,
+    // Per-request timeout, covering the whole download, and how often failed
+    // requests are retried. None waits as long as it takes.
+    pub fetch_timeout: Option<Duration>,
+    pub fetch_retries: u32,
+    // How long a cached registry document is used without revalidating it
+    pub metadata_max_age: Duration,
+    pub network: NetworkMode,
+    pub linker: Linker,
+    // Scoped registries and credentials from .npmrc files
+    pub npmrc: Npmrc,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            registry: DEFAULT_REGISTRY.to_string(),
+            cache_dir: home_dir().unwrap_or_default().join(".a-pm-cache"),
+            max_concurrent: std::cmp::min(num_cpus::get() * 2, 8),
+            fetch_timeout: Some(Duration::from_secs(5 * 60)),
+            fetch_retries: 3,
+            metadata_max_age: Duration::from_secs(5 * 60),
+            network: NetworkMode::Online,
+            linker: Linker::Hoisted,
+            npmrc: Npmrc::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config> {
+        Config::from_sources(|key| std::env::var(key).ok(), Npmrc::load()?)
+    }
+
+    // Build a config from .npmrc settings and an environment lookup. The
+    // environment wins; empty values are ignored.
+    pub fn from_sources(var: impl Fn(&str) -> Option<String>, npmrc: Npmrc) -> Result<Config> {
+        let mut config = Config::default();
+        let var = |key: &str| var(key).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+        if let Some(registry) = &npmrc.registry {
+            config.registry = registry.clone();
+        }
+        if let Some(timeout) = npmrc.fetch_timeout {
+            // Like npm, 0 means no timeout
+            config.fetch_timeout = (timeout > 0).then(|| Duration::from_millis(timeout));
+        }
+        if let Some(retries) = npmrc.fetch_retries {
+            config.fetch_retries = retries;
+        }
+        config.npmrc = npmrc;
+
+        if let Some(registry) = var("A_REGISTRY") {
+            config.registry = registry.trim_end_matches('/').to_string();
+        }
+
+        if let Some(cache_dir) = var("A_CACHE_DIR") {
+            config.cache_dir = expand_home(&cache_dir);
+        }
+
+        if let Some(max) = var("A_MAX_CONCURRENT") {
+            config.max_concurrent = match max.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => anyhow::bail!("A_MAX_CONCURRENT must be a positive number, got '{}'", max),
+            };
+        }
+
+        // 0 means no timeout, like fetch-timeout in .npmrc
+        if let Some(timeout) = var("A_FETCH_TIMEOUT") {
+            let timeout = timeout.parse::<u64>()
+                .map_err(|_| anyhow::anyhow!("A_FETCH_TIMEOUT must be a number of seconds, got '{}'", timeout))?;
+            config.fetch_timeout = (timeout > 0).then(|| Duration::from_secs(timeout));
+        }
+
+        if let Some(retries) = var("A_FETCH_RETRIES") {
+            config.fetch_retries = retries.parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("A_FETCH_RETRIES must be a number, got '{}'", retries))?;
+        }
+
+        if let Some(linker) = var("A_NODE_LINKER").or_else(|| config.npmrc.node_linker.clone()) {
+            config.linker = Linker::parse(&linker)
+                .ok_or_else(|| anyhow::anyhow!("node linker must be 'hoisted' or 'isolated', got '{}'", linker))?;
+        }
+
+        if let Some(max_age) = var("A_METADATA_MAX_AGE") {
+            config.metadata_max_age = max_age.parse::<u64>()
+                .map(Duration::from_secs)
+                .map_err(|_| anyhow::anyhow!("A_METADATA_MAX_AGE must be a number of seconds, got '{}'", max_age))?;
+        }
+
+        Ok(config)
+    }
+
+    // Registry serving a package: its scope's registry if one is configured
+    pub fn registry_for(&self, pkg: &str) -> &str {
+        pkg.strip_prefix('@')
+            .and_then(|_| pkg.split_once('/'))
+            .and_then(|(scope, _)| self.npmrc.scopes.get(scope))
+            .map(String::as_str)
+            .unwrap_or(&self.registry)
+    }
+
+    // Authorization header for a request to `url` made on behalf of
+    // `registry`. With `always-auth`, the registry's credentials are also
+    // sent to URLs on other hosts, such as tarball CDNs.
+    pub fn auth_header(&self, url: &str, registry: &str) -> Option<String> {
+        if let Some(header) = self.npmrc.credentials_for(url).and_then(|c| c.header()) {
+            return Some(header);
+        }
+        if self.npmrc.always_auth {
+            return self.npmrc.credentials_for(registry).and_then(|c| c.header());
+        }
+        None
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// Install the configuration for this process. Only the first call has an effect.
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn get() -> &'static Config {
+    // Falls back to the environment when `init` was never called (e.g. in tests)
+    CONFIG.get_or_init(|| Config::from_env().unwrap_or_default())
+}
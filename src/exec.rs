@@ -0,0 +1,95 @@
+use anyhow::Result;
+use colored::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::lockfile::{self, Lockfile};
+
+// `a exec` and `a dlx`: running package commands without adding the package
+// to the project. `a dlx` installs into a directory in the cache, keyed by
+// the resolved version, so running the same tool again needs no download.
+
+// Marks a dlx directory whose install finished
+const COMPLETE: &str = ".complete";
+
+// Split "name@range" into its parts; the range defaults to "latest"
+pub fn parse_spec(spec: &str) -> (String, String) {
+    // A leading @ is the scope, not the version
+    let scope = if spec.starts_with('@') { 1 } else { 0 };
+    match spec[scope..].find('@') {
+        Some(at) => {
+            let (name, range) = (&spec[..scope + at], &spec[scope + at + 1..]);
+            (name.to_string(), if range.is_empty() { "latest".to_string() } else { range.to_string() })
+        }
+        None => (spec.to_string(), "latest".to_string()),
+    }
+}
+
+// The command `a dlx` runs for a package: its only command, or else the one
+// named after the package
+pub fn pick_bin(package: &str, bins: &BTreeMap<String, PathBuf>) -> Result<String> {
+    if bins.len() == 1 {
+        return Ok(bins.keys().next().cloned().unwrap_or_default());
+    }
+    let unscoped = package.rsplit('/').next().unwrap_or(package);
+    if bins.contains_key(unscoped) {
+        return Ok(unscoped.to_string());
+    }
+    if bins.is_empty() {
+        anyhow::bail!("{} has no commands to run", package);
+    }
+    anyhow::bail!("{} has several commands and none is named after it: {}",
+                  package, bins.keys().cloned().collect::<Vec<_>>().join(", "))
+}
+
+// The nearest node_modules/.bin entry for `bin`, looking in `dir` and its
+// ancestors like the PATH of scripts does
+pub fn find_bin(dir: &Path, bin: &str) -> Option<PathBuf> {
+    let file = if cfg!(windows) { format!("{}.cmd", bin) } else { bin.to_string() };
+    dir.ancestors()
+        .map(|d| d.join("node_modules").join(".bin").join(&file))
+        .find(|path| path.exists())
+}
+
+// Resolve and install `name@range` with its dependencies into a directory in
+// the cache, reusing a previous install of the same version. Returns the
+// directory holding node_modules.
+pub async fn install_temporary(name: &str, range: &str) -> Result<PathBuf> {
+    let deps = BTreeMap::from([(name.to_string(), range.to_string())]);
+    println!("{}", format!("🔍 Resolving {}@{} ...", name, range).blue());
+    let graph = crate::resolver::resolve_dependencies(&deps, &BTreeMap::new(), &HashMap::new()).await?;
+    let version = graph.roots.get(name).cloned()
+        .ok_or_else(|| anyhow::anyhow!("Could not resolve {}@{}", name, range))?;
+
+    let dir = crate::config::get().cache_dir.join("dlx").join(format!("{}@{}", name.replace('/', "+"), version));
+    if dir.join(COMPLETE).exists() {
+        return Ok(dir);
+    }
+    // Left over from an interrupted install
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    let placement = crate::layout::plan(&graph);
+    let roots = HashSet::from([name.to_string()]);
+    let lock = Lockfile::from_graph(&graph, &placement, &roots);
+
+    // Like the project install, only this platform's packages are installed
+    let platform = crate::platform::Platform::current();
+    let packages: Vec<(String, lockfile::LockedPackage)> = lock.packages.into_iter()
+        .filter(|(_, locked)| locked.supports(&platform))
+        .map(|(path, locked)| (dir.join(&path).to_string_lossy().into_owned(), locked))
+        .collect();
+    let paths: Vec<String> = packages.iter().map(|(path, _)| path.clone()).collect();
+
+    crate::cache::install_packages_concurrently(packages, crate::config::get().max_concurrent).await?;
+    for path in &paths {
+        let package = lockfile::package_name(path);
+        let node_modules = &path[..path.len() - package.len() - 1];
+        crate::bin::link_bins(Path::new(node_modules), package)?;
+    }
+
+    fs::write(dir.join(COMPLETE), "")?;
+    Ok(dir)
+}
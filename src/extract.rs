@@ -0,0 +1,133 @@
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+
+// Safe extraction of package tarballs. Nothing from the archive is trusted:
+// every entry is re-rooted under the destination, and anything that could
+// write outside of it (`..`, absolute paths, links) is skipped and reported.
+
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    pub files: usize,
+    // (path as it appeared in the tarball, reason it was skipped)
+    pub rejected: Vec<(String, String)>,
+}
+
+pub fn extract_package(tarball_bytes: &[u8], dest: &Path) -> Result<ExtractReport> {
+    let mut archive = Archive::new(GzDecoder::new(tarball_bytes));
+    let mut report = ExtractReport::default();
+
+    let entries = archive.entries()
+        .map_err(|e| anyhow::anyhow!("Failed to read tarball entries: {}", e))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| anyhow::anyhow!("Failed to read entry in tarball: {}", e))?;
+        let raw_path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let entry_type = entry.header().entry_type();
+
+        // Metadata entries are folded into the next entry by the tar crate
+        if matches!(entry_type, EntryType::XGlobalHeader | EntryType::XHeader
+            | EntryType::GNULongName | EntryType::GNULongLink) {
+            continue;
+        }
+
+        let rel_path = match sanitize_path(&raw_path) {
+            Ok(Some(rel_path)) => rel_path,
+            Ok(None) => continue, // The top-level folder itself
+            Err(reason) => {
+                report.rejected.push((raw_path, reason));
+                continue;
+            }
+        };
+        let target_path = dest.join(&rel_path);
+
+        match entry_type {
+            EntryType::Directory => {
+                fs::create_dir_all(&target_path)?;
+                set_mode(&target_path, 0o755)?;
+            }
+            EntryType::Regular | EntryType::Continuous => {
+                if let Some(parent) = target_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                // Files are always written fresh so nothing already on disk
+                // (like a planted symlink) can redirect the write
+                if fs::symlink_metadata(&target_path).is_ok() {
+                    fs::remove_file(&target_path)?;
+                }
+                let mut file = fs::File::create(&target_path)?;
+                io::copy(&mut entry, &mut file)?;
+
+                // Like npm, keep only whether the file is executable
+                let executable = entry.header().mode().map(|m| m & 0o111 != 0).unwrap_or(false);
+                set_mode(&target_path, if executable { 0o755 } else { 0o644 })?;
+                report.files += 1;
+            }
+            EntryType::Symlink | EntryType::Link => {
+                let target = entry.link_name_bytes()
+                    .map(|t| String::from_utf8_lossy(&t).into_owned())
+                    .unwrap_or_default();
+                report.rejected.push((raw_path, format!("link to '{}' is not allowed", target)));
+            }
+            other => {
+                report.rejected.push((raw_path, format!("unsupported entry type {:?}", other)));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+// Strip exactly the first path component, like `npm` does regardless of
+// whether it is called `package/`, and refuse anything that could escape
+// the package directory. Ok(None) means the entry is the top-level folder.
+pub fn sanitize_path(raw: &str) -> std::result::Result<Option<PathBuf>, String> {
+    let normalized = raw.replace('\\', "/");
+
+    if normalized.starts_with('/') || Path::new(&normalized).has_root() || has_drive_prefix(&normalized) {
+        return Err("absolute path".to_string());
+    }
+
+    let mut rel_path = PathBuf::new();
+    for (i, part) in normalized.split('/').filter(|p| !p.is_empty() && *p != ".").enumerate() {
+        if part == ".." {
+            return Err("path escapes the package directory".to_string());
+        }
+        if i == 0 {
+            continue;
+        }
+        rel_path.push(part);
+    }
+
+    // Anything that still isn't a plain relative path is refused
+    if rel_path.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err("path escapes the package directory".to_string());
+    }
+
+    if rel_path.as_os_str().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(rel_path))
+    }
+}
+
+// Windows paths like "C:/x" or "C:x"
+fn has_drive_prefix(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
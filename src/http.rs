@@ -0,0 +1,134 @@
+use anyhow::Result;
+use colored::*;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use std::sync::OnceLock;
+use crate::cache::NotCached;
+use crate::config::NetworkMode;
+use std::time::{Duration, SystemTime};
+
+// One HTTP client per process so connections to the registry are pooled and
+// kept alive, plus retries with exponential backoff for transient failures.
+
+// Never wait longer than this between attempts, even if a server asks to
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Attempts after the first one
+    pub retries: u32,
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn from_config() -> RetryPolicy {
+        RetryPolicy {
+            retries: crate::config::get().fetch_retries,
+            min_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+
+    // 0.5s, 1s, 2s, ... capped at max_delay
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        std::cmp::min(self.min_delay.saturating_mul(factor), self.max_delay)
+    }
+}
+
+// A fully read response; the body is part of the retried operation so a
+// connection dropped halfway through a download is retried too
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+pub fn client() -> &'static Client {
+    CLIENT.get_or_init(|| {
+        let config = crate::config::get();
+        let mut builder = Client::builder();
+        if let Some(timeout) = config.fetch_timeout {
+            builder = builder.timeout(timeout);
+        }
+        builder
+            .user_agent(concat!("a-pm/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(10))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(config.max_concurrent)
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .expect("failed to build HTTP client")
+    })
+}
+
+// GET with the shared client. `build` is called for every attempt so it can
+// attach headers; only idempotent requests should go through here.
+pub async fn get(build: impl Fn(&Client) -> RequestBuilder) -> Result<HttpResponse> {
+    if crate::config::get().network == NetworkMode::Offline {
+        let url = build(client()).build()?.url().to_string();
+        return Err(NotCached(url).into());
+    }
+    get_with(client(), &RetryPolicy::from_config(), build).await
+}
+
+pub async fn get_with(
+    client: &Client,
+    policy: &RetryPolicy,
+    build: impl Fn(&Client) -> RequestBuilder,
+) -> Result<HttpResponse> {
+    let mut attempt = 0;
+    loop {
+        let request = build(client).build()?;
+        let url = request.url().to_string();
+
+        let outcome = match client.execute(request).await {
+            Ok(resp) => {
+                let status = resp.status();
+                let headers = resp.headers().clone();
+                resp.bytes().await.map(|body| HttpResponse { status, headers, body: body.to_vec() })
+            }
+            Err(e) => Err(e),
+        };
+
+        let (delay, reason) = match outcome {
+            Ok(resp) if is_retryable_status(resp.status) && attempt < policy.retries => {
+                let delay = retry_after(&resp.headers).unwrap_or_else(|| policy.backoff(attempt));
+                (delay, format!("status {}", resp.status))
+            }
+            Ok(resp) => return Ok(resp),
+            Err(e) if is_transient(&e) && attempt < policy.retries => (policy.backoff(attempt), e.to_string()),
+            Err(e) => return Err(anyhow::anyhow!("Request to {} failed: {}", url, e)),
+        };
+
+        attempt += 1;
+        eprintln!("{}", format!("⚠️ Request to {} failed ({}), retrying in {:.1}s ({}/{})",
+                  url, reason, delay.as_secs_f64(), attempt, policy.retries).yellow());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+}
+
+// Retry-After is either a number of seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO)
+        }
+    };
+    Some(std::cmp::min(delay, MAX_RETRY_AFTER))
+}
@@ -0,0 +1,148 @@
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use crate::registry::NpmDist;
+
+// Subresource Integrity (https://w3c.github.io/webappsec-subresource-integrity/)
+// checks for package tarballs, plus npm's legacy hex sha1 `shasum`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Algorithm {
+    // Ordered from weakest to strongest
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Algorithm> {
+        match name {
+            "sha1" => Some(Algorithm::Sha1),
+            "sha256" => Some(Algorithm::Sha256),
+            "sha384" => Some(Algorithm::Sha384),
+            "sha512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    pub fn digest(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Sha1 => Sha1::digest(bytes).to_vec(),
+            Algorithm::Sha256 => Sha256::digest(bytes).to_vec(),
+            Algorithm::Sha384 => Sha384::digest(bytes).to_vec(),
+            Algorithm::Sha512 => Sha512::digest(bytes).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hash {
+    pub algorithm: Algorithm,
+    pub digest: Vec<u8>,
+}
+
+impl std::fmt::Display for Hash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.algorithm.name(), STANDARD.encode(&self.digest))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Integrity {
+    hashes: Vec<Hash>,
+}
+
+impl Integrity {
+    // Parse an SRI string such as "sha512-abc... sha1-def...". Unknown
+    // algorithms and options (`?foo`) are ignored as the spec requires.
+    pub fn parse(sri: &str) -> Result<Integrity> {
+        let mut hashes = Vec::new();
+        for token in sri.split_whitespace() {
+            let Some((name, rest)) = token.split_once('-') else { continue };
+            let Some(algorithm) = Algorithm::from_name(name) else { continue };
+            let encoded = rest.split('?').next().unwrap_or("");
+            let digest = STANDARD.decode(encoded)
+                .map_err(|e| anyhow::anyhow!("Invalid {} integrity hash '{}': {}", name, encoded, e))?;
+            hashes.push(Hash { algorithm, digest });
+        }
+
+        if hashes.is_empty() {
+            anyhow::bail!("No supported hash found in integrity string '{}'", sri);
+        }
+        Ok(Integrity { hashes })
+    }
+
+    // Legacy registry documents only have a hex encoded sha1 `shasum`
+    pub fn from_shasum(shasum: &str) -> Result<Integrity> {
+        let shasum = shasum.trim();
+        if shasum.len() != 40 || !shasum.is_ascii() {
+            anyhow::bail!("Invalid shasum '{}'", shasum);
+        }
+        let digest = (0..shasum.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&shasum[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| anyhow::anyhow!("Invalid shasum '{}'", shasum))?;
+        Ok(Integrity { hashes: vec![Hash { algorithm: Algorithm::Sha1, digest }] })
+    }
+
+    // Expected integrity for a registry tarball, preferring the SRI string
+    pub fn for_dist(dist: &NpmDist) -> Result<Option<Integrity>> {
+        if let Some(sri) = dist.integrity.as_deref().filter(|s| !s.trim().is_empty()) {
+            return Integrity::parse(sri).map(Some);
+        }
+        if !dist.shasum.trim().is_empty() {
+            return Integrity::from_shasum(&dist.shasum).map(Some);
+        }
+        Ok(None)
+    }
+
+    // SHA-512 of some content, for tarballs the registry gave no hash for
+    pub fn of(bytes: &[u8]) -> Integrity {
+        let algorithm = Algorithm::Sha512;
+        Integrity { hashes: vec![Hash { algorithm, digest: algorithm.digest(bytes) }] }
+    }
+
+    // File-name safe identifier of the content, from its strongest hash
+    pub fn content_key(&self) -> String {
+        let algorithm = self.strongest();
+        let digest = self.hashes.iter()
+            .find(|h| h.algorithm == algorithm)
+            .map(|h| h.digest.as_slice())
+            .unwrap_or_default();
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}-{}", algorithm.name(), hex)
+    }
+
+    fn strongest(&self) -> Algorithm {
+        self.hashes.iter().map(|h| h.algorithm).max().unwrap_or(Algorithm::Sha512)
+    }
+
+    // Only the strongest algorithm present is checked; any of its hashes may
+    // match. Returns a description of the mismatch on failure.
+    pub fn check(&self, bytes: &[u8]) -> std::result::Result<(), String> {
+        let algorithm = self.strongest();
+        let actual = algorithm.digest(bytes);
+        let mut expected = self.hashes.iter().filter(|h| h.algorithm == algorithm);
+
+        if expected.clone().any(|h| h.digest == actual) {
+            return Ok(());
+        }
+
+        let expected = expected.next().map(|h| h.to_string()).unwrap_or_default();
+        let actual = Hash { algorithm, digest: actual };
+        Err(format!("expected {}, got {}", expected, actual))
+    }
+}
@@ -0,0 +1,97 @@
+use crate::lockfile::{enclosing_paths, install_path, resolve_path};
+use crate::resolver::{node_key, ResolvedGraph};
+use crate::semver_range;
+use std::collections::{HashMap, VecDeque};
+
+// Decide where every package of a resolved graph goes in node_modules.
+//
+// Top-level dependencies are installed at `node_modules/<name>`. For every
+// other package, the version most packages ask for is hoisted to the top
+// level as well. A package that would find the wrong version of a dependency
+// through Node's resolution gets the right one nested in its own
+// `node_modules` folder instead.
+//
+// Returns install path -> "name@version" node key.
+pub fn plan(graph: &ResolvedGraph) -> HashMap<String, String> {
+    let hoisted = hoisted_versions(graph);
+    let mut placement: HashMap<String, String> = HashMap::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+
+    for (name, version) in &graph.roots {
+        let path = install_path(name);
+        placement.insert(path.clone(), node_key(name, version));
+        queue.push_back(path);
+    }
+
+    // Breadth first, so everything nested under a package is placed before
+    // any package inside its folder resolves its own dependencies
+    while let Some(path) = queue.pop_front() {
+        let Some(node) = graph.packages.get(&placement[&path]) else { continue };
+
+        for (dep, version) in node.dependencies.iter().chain(&node.optional_dependencies) {
+            let key = node_key(dep, version);
+            let target = match resolve_path(&path, dep, |p| placement.contains_key(p)) {
+                Some(found) if placement[&found] == key => continue,
+                None if hoisted.get(dep) == Some(version) => install_path(dep),
+                // In a cycle between versions, nesting would never end; the
+                // dependency is already installed further up
+                _ if on_chain(&path, &key, &placement) => continue,
+                _ => format!("{}/node_modules/{}", path, dep),
+            };
+            placement.insert(target.clone(), key);
+            queue.push_back(target);
+        }
+
+        // Peers come from the top level; one nothing else placed is added there
+        for peer in node.peer_dependencies.keys() {
+            if resolve_path(&path, peer, |p| placement.contains_key(p)).is_some() {
+                continue;
+            }
+            if let Some(version) = hoisted.get(peer) {
+                let target = install_path(peer);
+                placement.insert(target.clone(), node_key(peer, version));
+                queue.push_back(target);
+            }
+        }
+    }
+
+    placement
+}
+
+// Whether `key` is installed at `path` or at one of the folders it is nested
+// in. `Lockfile::verify` accepts the same dependency in a cycle.
+fn on_chain(path: &str, key: &str, placement: &HashMap<String, String>) -> bool {
+    enclosing_paths(path).any(|ancestor| placement.get(ancestor).map(String::as_str) == Some(key))
+}
+
+// Package name -> version to install at the top level: the root version for
+// top-level dependencies, otherwise the version with the most dependents,
+// preferring the newer one on a tie
+fn hoisted_versions(graph: &ResolvedGraph) -> HashMap<String, String> {
+    let mut dependents: HashMap<(&str, &str), usize> = HashMap::new();
+    for node in graph.packages.values() {
+        dependents.entry((&node.name, &node.version)).or_default();
+        for (dep, version) in node.dependencies.iter().chain(&node.optional_dependencies) {
+            *dependents.entry((dep, version)).or_default() += 1;
+        }
+    }
+
+    let mut best: HashMap<&str, (&str, usize)> = HashMap::new();
+    for ((name, version), count) in dependents {
+        let better = match best.get(name) {
+            Some(&(current, current_count)) => {
+                count > current_count || (count == current_count && semver_range::is_newer(version, current))
+            }
+            None => true,
+        };
+        if better {
+            best.insert(name, (version, count));
+        }
+    }
+
+    let mut hoisted: HashMap<String, String> = best.into_iter()
+        .map(|(name, (version, _))| (name.to_string(), version.to_string()))
+        .collect();
+    hoisted.extend(graph.roots.clone());
+    hoisted
+}
@@ -1,39 +1,468 @@
+use crate::platform::Platform;
+use crate::registry::{NpmDist, PeerDependencyMeta};
+use crate::resolver::{self, ResolvedGraph};
+use crate::semver_range;
+use colored::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 
-#[derive(Serialize, Deserialize, Default)]
+pub const LOCKFILE_VERSION: u32 = 2;
+
+// Everything needed to reinstall one package without asking the registry again
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct LockedPackage {
+    pub version: String,
+    // Tarball URL the package was resolved to
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub resolved: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shasum: Option<String>,
+    // Dependency name -> exact version it was resolved to
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "optionalDependencies", skip_serializing_if = "BTreeMap::is_empty")]
+    pub optional_dependencies: BTreeMap<String, String>,
+    // Peer name -> range, as published
+    #[serde(default, rename = "peerDependencies", skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "peerDependenciesMeta", skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_dependencies_meta: BTreeMap<String, PeerDependencyMeta>,
+    // Platforms the package is restricted to. Packages for other platforms
+    // stay in the lockfile but are not installed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub libc: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dev: bool,
+    // Only needed through optional dependencies
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+}
+
+impl LockedPackage {
+    // Tarball details for installing, if this entry has been resolved
+    pub fn dist(&self) -> Option<NpmDist> {
+        if self.resolved.is_empty() {
+            return None;
+        }
+        Some(NpmDist {
+            tarball: self.resolved.clone(),
+            shasum: self.shasum.clone().unwrap_or_default(),
+            integrity: self.integrity.clone(),
+        })
+    }
+
+    pub fn supports(&self, platform: &Platform) -> bool {
+        platform.supports(&self.os, &self.cpu, &self.libc)
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Lockfile {
-    dependencies: HashMap<String, String>,
+    #[serde(rename = "lockfileVersion")]
+    pub lockfile_version: u32,
+    // Install path (e.g. "node_modules/express") -> locked package. Sorted so
+    // the file is stable between runs and diffs stay small.
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile { lockfile_version: LOCKFILE_VERSION, packages: BTreeMap::new() }
+    }
+}
+
+// The original lockfile format only mapped package names to versions
+#[derive(Deserialize)]
+struct LockfileV1 {
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    // Build a lockfile from a resolved graph. `placement` maps each install
+    // path to the "name@version" node installed there. Anything not reachable
+    // from `prod_roots` is marked as a dev dependency, and anything only
+    // reachable through optional dependencies as optional.
+    pub fn from_graph(
+        graph: &ResolvedGraph,
+        placement: &HashMap<String, String>,
+        prod_roots: &HashSet<String>,
+    ) -> Lockfile {
+        let prod = reachable(graph, placement, true, graph.roots.iter()
+            .filter(|(name, _)| prod_roots.contains(*name)));
+        let required = reachable(graph, placement, false, graph.roots.iter()
+            .filter(|(name, _)| !graph.optional_roots.contains(*name)));
+
+        let mut lock = Lockfile::default();
+        for (path, key) in placement {
+            let Some(node) = graph.packages.get(key) else { continue };
+            lock.packages.insert(path.clone(), LockedPackage {
+                version: node.version.clone(),
+                resolved: node.dist.tarball.clone(),
+                integrity: node.dist.integrity.clone(),
+                shasum: Some(node.dist.shasum.clone()).filter(|s| !s.is_empty()),
+                dependencies: node.dependencies.clone(),
+                optional_dependencies: node.optional_dependencies.clone(),
+                peer_dependencies: node.peer_dependencies.clone(),
+                peer_dependencies_meta: node.peer_dependencies_meta.clone(),
+                os: node.os.clone(),
+                cpu: node.cpu.clone(),
+                libc: node.libc.clone(),
+                dev: !prod.contains(key),
+                optional: !required.contains(key),
+            });
+        }
+        lock
+    }
+}
+
+// Node keys reachable from `roots` through dependencies and peers, and also
+// through optional dependencies when `include_optional` is set
+fn reachable<'a>(
+    graph: &ResolvedGraph,
+    placement: &HashMap<String, String>,
+    include_optional: bool,
+    roots: impl Iterator<Item = (&'a String, &'a String)>,
+) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut stack: Vec<String> = roots.map(|(name, version)| resolver::node_key(name, version)).collect();
+
+    while let Some(key) = stack.pop() {
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        let Some(node) = graph.packages.get(&key) else { continue };
+
+        let optional = node.optional_dependencies.iter().filter(|_| include_optional);
+        for (dep, version) in node.dependencies.iter().chain(optional) {
+            stack.push(resolver::node_key(dep, version));
+        }
+        // Peers are provided at the top level
+        for peer in node.peer_dependencies.keys() {
+            if let Some(peer_key) = placement.get(&install_path(peer)) {
+                stack.push(peer_key.clone());
+            }
+        }
+    }
+    seen
+}
+
+impl Lockfile {
+    // Find the copy of `name` that the package installed at `from` would load
+    pub fn find_dependency(&self, from: &str, name: &str) -> Option<(String, &LockedPackage)> {
+        let path = resolve_path(from, name, |path| self.packages.contains_key(path))?;
+        let pkg = &self.packages[&path];
+        Some((path, pkg))
+    }
+
+    // Whether `name@version` is installed at `path` or at one of the folders
+    // it is nested in. In a cycle between versions, `layout::plan` leaves the
+    // dependency there instead of nesting it forever.
+    pub fn on_chain(&self, path: &str, name: &str, version: &str) -> bool {
+        enclosing_paths(path).any(|p| {
+            package_name(p) == name && self.packages.get(p).is_some_and(|pkg| pkg.version == version)
+        })
+    }
+
+    // Install paths ordered so every package comes after the packages it
+    // depends on, for running install scripts. Cycles are broken arbitrarily.
+    pub fn dependency_order(&self) -> Vec<String> {
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        for path in self.packages.keys() {
+            self.visit(path, &mut visited, &mut order);
+        }
+        order
+    }
+
+    fn visit(&self, path: &str, visited: &mut HashSet<String>, order: &mut Vec<String>) {
+        if !visited.insert(path.to_string()) {
+            return;
+        }
+        let locked = &self.packages[path];
+        let deps = locked.dependencies.keys()
+            .chain(locked.optional_dependencies.keys())
+            .chain(locked.peer_dependencies.keys());
+        for dep in deps {
+            if let Some((dep_path, _)) = self.find_dependency(path, dep) {
+                self.visit(&dep_path, visited, order);
+            }
+        }
+        order.push(path.to_string());
+    }
+
+    // Everything that keeps this lockfile from being installed as-is for a
+    // manifest with these dependencies. Empty when it is up to date. Optional
+    // dependencies may be missing, e.g. when they failed to resolve.
+    pub fn verify(&self, deps: &BTreeMap<String, String>, optional_deps: &BTreeMap<String, String>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut reachable = HashSet::new();
+        let mut stack = Vec::new();
+
+        let roots = deps.iter().map(|dep| (dep, false)).chain(optional_deps.iter().map(|dep| (dep, true)));
+        for ((name, req), optional) in roots {
+            let Some((path, pkg)) = self.find_dependency("", name) else {
+                if !optional {
+                    problems.push(format!("{} is missing", name));
+                }
+                continue;
+            };
+            // Dist-tags and other non-range specs can't be checked offline
+            if let Ok(range) = semver_range::Range::parse(req) {
+                if !range.satisfies_str(&pkg.version) {
+                    problems.push(format!("{} is locked at {}, which does not satisfy {}", name, pkg.version, req));
+                }
+            }
+            stack.push(path);
+        }
+
+        while let Some(path) = stack.pop() {
+            if !reachable.insert(path.clone()) {
+                continue;
+            }
+            let pkg = &self.packages[&path];
+            let edges = pkg.dependencies.iter().map(|dep| (dep, false))
+                .chain(pkg.optional_dependencies.iter().map(|dep| (dep, true)));
+            for ((dep, version), optional) in edges {
+                let Some((dep_path, locked)) = self.find_dependency(&path, dep) else {
+                    if !optional {
+                        problems.push(format!("{} needs {}@{}, which is missing", path, dep, version));
+                    }
+                    continue;
+                };
+                if &locked.version != version && !self.on_chain(&path, dep, version) {
+                    problems.push(format!("{} needs {}@{}, but {} is locked", path, dep, version, locked.version));
+                }
+                stack.push(dep_path);
+            }
+
+            // A peer's version is not recorded; it only has to be there
+            for peer in pkg.peer_dependencies.keys() {
+                let optional = pkg.peer_dependencies_meta.get(peer).map(|m| m.optional).unwrap_or(false);
+                match self.find_dependency(&path, peer) {
+                    Some((peer_path, _)) => stack.push(peer_path),
+                    None if optional => {}
+                    None => problems.push(format!("{} needs peer {}, which is missing", path, peer)),
+                }
+            }
+        }
+
+        for (path, pkg) in &self.packages {
+            if !reachable.contains(path) {
+                problems.push(format!("{} is locked but no longer depended on", path));
+            } else if pkg.dist().is_none() {
+                problems.push(format!("{} has no tarball information", path));
+            }
+        }
+
+        problems
+    }
+}
+
+// Node's module resolution: the first of `<from>/node_modules/<name>`,
+// `<parent of from>/node_modules/<name>`, ... `node_modules/<name>` for which
+// `exists` is true. An empty `from` means the project root.
+pub fn resolve_path(from: &str, name: &str, exists: impl Fn(&str) -> bool) -> Option<String> {
+    let mut base = from.to_string();
+    loop {
+        let candidate = if base.is_empty() {
+            install_path(name)
+        } else {
+            format!("{}/node_modules/{}", base, name)
+        };
+        if exists(&candidate) {
+            return Some(candidate);
+        }
+        if base.is_empty() {
+            return None;
+        }
+        base = match base.rfind("/node_modules/") {
+            Some(i) => base[..i].to_string(),
+            None => String::new(),
+        };
+    }
+}
+
+// Install path for a top-level package
+pub fn install_path(pkg: &str) -> String {
+    format!("node_modules/{}", pkg)
+}
+
+// `path` and the install paths it is nested in, innermost first:
+// "node_modules/a/node_modules/b", then "node_modules/a"
+pub fn enclosing_paths(path: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(path).chain(path.rmatch_indices("/node_modules/").map(move |(at, _)| &path[..at]))
+}
+
+// Package name from an install path like "node_modules/a/node_modules/@scope/b"
+pub fn package_name(path: &str) -> &str {
+    path.rsplit_once("node_modules/").map(|(_, name)| name).unwrap_or(path)
 }
 
 pub fn add_dependency(pkg: &str, version: &str) -> anyhow::Result<()> {
-    let mut lock = read_or_new()?;
-    lock.dependencies.insert(pkg.to_string(), version.to_string());
-    save_lockfile(&lock)
+    let mut lock = read_lockfile()?;
+    let entry = lock.packages.entry(install_path(pkg)).or_default();
+    if entry.version != version {
+        // The old tarball details no longer apply to the new version
+        *entry = LockedPackage { version: version.to_string(), ..Default::default() };
+    }
+    write_lockfile(&lock)
 }
 
 pub fn remove_dependency(pkg: &str) -> anyhow::Result<()> {
-    let mut lock = read_or_new()?;
-    lock.dependencies.remove(pkg);
-    save_lockfile(&lock)
+    let mut lock = read_lockfile()?;
+    let path = install_path(pkg);
+    let nested = format!("{}/", path);
+    lock.packages.retain(|key, _| key != &path && !key.starts_with(&nested));
+    write_lockfile(&lock)
 }
 
+// Top-level package name -> locked version
 pub fn read_dependencies() -> anyhow::Result<HashMap<String, String>> {
-    let lock = read_or_new()?;
-    Ok(lock.dependencies)
+    let lock = read_lockfile()?;
+    Ok(lock.packages.into_iter()
+        .filter(|(path, _)| path.matches("node_modules/").count() == 1)
+        .map(|(path, pkg)| (package_name(&path).to_string(), pkg.version))
+        .collect())
+}
+
+pub fn read_lockfile() -> anyhow::Result<Lockfile> {
+    match fs::read_to_string("a.lock") {
+        Ok(content) => parse_lockfile(&content),
+        Err(_) => Ok(Lockfile::default()),
+    }
+}
+
+// The lockfile exactly as committed: it must exist and be free of conflicts
+pub fn read_frozen_lockfile() -> anyhow::Result<Lockfile> {
+    let content = fs::read_to_string("a.lock")
+        .map_err(|_| anyhow::anyhow!("a.lock not found; run `a install` to create it"))?;
+    if split_conflict(&content).is_some() {
+        anyhow::bail!("a.lock has merge conflicts; run `a install` to resolve them");
+    }
+    parse_lockfile(&content)
+}
+
+pub fn parse_lockfile(content: &str) -> anyhow::Result<Lockfile> {
+    // A git merge left conflict markers behind: combine both sides. The next
+    // install re-resolves against the manifest and writes a clean file.
+    if let Some((ours, theirs)) = split_conflict(content) {
+        println!("{}", "🔀 a.lock has merge conflicts, merging both sides...".yellow());
+        let ours = parse_lockfile(&ours)
+            .map_err(|e| anyhow::anyhow!("Failed to parse our side of the a.lock conflict: {}", e))?;
+        let theirs = parse_lockfile(&theirs)
+            .map_err(|e| anyhow::anyhow!("Failed to parse their side of the a.lock conflict: {}", e))?;
+        return Ok(merge(ours, theirs));
+    }
+
+    let value: serde_json::Value = serde_json::from_str(content)?;
+
+    match value.get("lockfileVersion").and_then(|v| v.as_u64()) {
+        None => {
+            println!("{}", "🔄 Migrating a.lock from v1 to v2".yellow());
+            let old: LockfileV1 = serde_json::from_value(value)?;
+            Ok(migrate_v1(old))
+        }
+        Some(v) if v == LOCKFILE_VERSION as u64 => Ok(serde_json::from_value(value)?),
+        Some(v) => Err(anyhow::anyhow!(
+            "a.lock uses lockfileVersion {}, but this version of a only understands up to {}",
+            v, LOCKFILE_VERSION
+        )),
+    }
+}
+
+// Split a file containing git conflict markers into the "ours" and "theirs"
+// versions. Returns None when there are no conflicts.
+pub fn split_conflict(content: &str) -> Option<(String, String)> {
+    enum Side { Both, Ours, Base, Theirs }
+
+    if !content.lines().any(|line| line.starts_with("<<<<<<<")) {
+        return None;
+    }
+
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut side = Side::Both;
+
+    for line in content.lines() {
+        if line.starts_with("<<<<<<<") {
+            side = Side::Ours;
+            continue;
+        }
+        if line.starts_with("|||||||") {
+            // diff3 style: the common ancestor is not needed
+            side = Side::Base;
+            continue;
+        }
+        if line.starts_with("=======") {
+            side = Side::Theirs;
+            continue;
+        }
+        if line.starts_with(">>>>>>>") {
+            side = Side::Both;
+            continue;
+        }
+
+        match side {
+            Side::Both => {
+                ours.push_str(line);
+                ours.push('\n');
+                theirs.push_str(line);
+                theirs.push('\n');
+            }
+            Side::Ours => {
+                ours.push_str(line);
+                ours.push('\n');
+            }
+            Side::Theirs => {
+                theirs.push_str(line);
+                theirs.push('\n');
+            }
+            Side::Base => {}
+        }
+    }
+
+    Some((ours, theirs))
+}
+
+// Union of two lockfiles; when both lock the same path, the newer version wins
+fn merge(ours: Lockfile, theirs: Lockfile) -> Lockfile {
+    let mut merged = ours;
+    for (path, pkg) in theirs.packages {
+        match merged.packages.get(&path) {
+            Some(existing) if !semver_range::is_newer(&pkg.version, &existing.version) => {}
+            _ => {
+                merged.packages.insert(path, pkg);
+            }
+        }
+    }
+    merged
 }
 
-fn read_or_new() -> anyhow::Result<Lockfile> {
-    if let Ok(content) = fs::read_to_string("a.lock") {
-        Ok(serde_json::from_str(&content)?)
-    } else {
-        Ok(Lockfile::default())
+// v1 entries only carry a version; the tarball URL and integrity are filled
+// in the next time the package is resolved.
+fn migrate_v1(old: LockfileV1) -> Lockfile {
+    let mut lock = Lockfile::default();
+    for (name, version) in old.dependencies {
+        lock.packages.insert(install_path(&name), LockedPackage { version, ..Default::default() });
     }
+    lock
 }
 
-fn save_lockfile(lock: &Lockfile) -> anyhow::Result<()> {
-    let content = serde_json::to_string_pretty(lock)?;
+pub fn write_lockfile(lock: &Lockfile) -> anyhow::Result<()> {
+    let mut content = serde_json::to_string_pretty(lock)?;
+    content.push('\n');
     fs::write("a.lock", content)?;
     Ok(())
 }
@@ -4,12 +4,106 @@ mod lockfile;
 mod manifest;
 mod resolver;
 mod cache;
+mod semver_range;
+mod integrity;
+mod extract;
+mod config;
+mod npmrc;
+mod http;
+mod metadata;
+mod platform;
+mod layout;
+mod store;
+mod bin;
+mod scripts;
+mod exec;
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     include!("../tests/basic_tests.rs");
 }
 
+#[cfg(test)]
+mod bin_tests {
+    include!("../tests/bin_tests.rs");
+}
+
+#[cfg(test)]
+mod config_tests {
+    include!("../tests/config_tests.rs");
+}
+
+#[cfg(test)]
+mod exec_tests {
+    include!("../tests/exec_tests.rs");
+}
+
+#[cfg(test)]
+mod extract_tests {
+    include!("../tests/extract_tests.rs");
+}
+
+#[cfg(test)]
+mod http_tests {
+    include!("../tests/http_tests.rs");
+}
+
+#[cfg(test)]
+mod integrity_tests {
+    include!("../tests/integrity_tests.rs");
+}
+
+#[cfg(test)]
+mod layout_tests {
+    include!("../tests/layout_tests.rs");
+}
+
+#[cfg(test)]
+mod lockfile_tests {
+    include!("../tests/lockfile_tests.rs");
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    include!("../tests/manifest_tests.rs");
+}
+
+#[cfg(test)]
+mod metadata_tests {
+    include!("../tests/metadata_tests.rs");
+}
+
+#[cfg(test)]
+mod npmrc_tests {
+    include!("../tests/npmrc_tests.rs");
+}
+
+#[cfg(test)]
+mod platform_tests {
+    include!("../tests/platform_tests.rs");
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    include!("../tests/resolver_tests.rs");
+}
+
+#[cfg(test)]
+mod scripts_tests {
+    include!("../tests/scripts_tests.rs");
+}
+
+#[cfg(test)]
+mod semver_range_tests {
+    include!("../tests/semver_range_tests.rs");
+}
+
+#[cfg(test)]
+mod store_tests {
+    include!("../tests/store_tests.rs");
+}
+
 use clap::{Parser, Subcommand};
 use colored::*;
 
@@ -26,6 +120,14 @@ struct Cli {
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
+
+    /// Never touch the network; everything must come from the cache
+    #[arg(long, global = true, conflicts_with = "prefer_offline")]
+    offline: bool,
+
+    /// Use cached registry data without checking whether it is stale
+    #[arg(long, global = true)]
+    prefer_offline: bool,
 }
 
 #[derive(Subcommand)]
@@ -34,22 +136,61 @@ enum Commands {
         package: String,
         #[arg(short, long)]
         dev: bool,
+        /// Don't run install scripts, neither the project's nor those of dependencies
+        #[arg(long)]
+        ignore_scripts: bool,
+    },
+    Install {
+        /// Fail instead of updating a.lock when it doesn't match package.json
+        #[arg(long)]
+        frozen_lockfile: bool,
+        /// Don't run install scripts, neither the project's nor those of dependencies
+        #[arg(long)]
+        ignore_scripts: bool,
+    },
+    /// Clean install of exactly what a.lock records, for CI
+    Ci {
+        /// Don't run install scripts, neither the project's nor those of dependencies
+        #[arg(long)]
+        ignore_scripts: bool,
     },
-    Install,
     Remove { 
         package: String,
         #[arg(short, long)]
         dev: bool,
+        /// Don't run install scripts, neither the project's nor those of dependencies
+        #[arg(long)]
+        ignore_scripts: bool,
     },
     Search { query: String },
     Init {
         name: Option<String>,
     },
+    /// Run a script from package.json, or list them
     Run {
-        script: String,
+        script: Option<String>,
+        /// Extra arguments for the script, e.g. `a run test -- --watch`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run a command installed in node_modules/.bin
+    Exec {
+        bin: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run a package's command without adding it to the project
+    Dlx {
+        /// The package, optionally with a version range: name@range
+        package: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
     },
     Update {
         package: Option<String>,
+        /// Don't run install scripts, neither the project's nor those of dependencies
+        #[arg(long)]
+        ignore_scripts: bool,
     },
     List,
     Cache {
@@ -81,6 +222,28 @@ async fn main() {
     // Setup logging based on verbose flag
     let verbose = cli.verbose;
 
+    // Read configuration from the environment once, before anything uses it
+    match config::Config::from_env() {
+        Ok(mut config) => {
+            if cli.offline {
+                config.network = config::NetworkMode::Offline;
+            } else if cli.prefer_offline {
+                config.network = config::NetworkMode::PreferOffline;
+            }
+            config::init(config)
+        }
+        Err(e) => {
+            eprintln!("{}", format!("❌ Invalid configuration: {}", e).red());
+            std::process::exit(1);
+        }
+    }
+
+    // Signals stop `a`, or the script it is running
+    if let Err(e) = scripts::handle_signals() {
+        eprintln!("{}", format!("❌ Failed to set up signal handling: {}", e).red());
+        std::process::exit(1);
+    }
+
     // Ensure cache directories exist
     if let Err(e) = cache::ensure_cache_dirs() {
         eprintln!("{}", format!("❌ Failed to initialize cache: {}", e).red());
@@ -89,13 +252,16 @@ async fn main() {
 
     // Execute command
     let result = match cli.command {
-        Commands::Add { package, dev } => commands::add(&package, dev).await,
-        Commands::Install => commands::install().await,
-        Commands::Remove { package, dev } => commands::remove(&package, dev).await,
+        Commands::Add { package, dev, ignore_scripts } => commands::add(&package, dev, ignore_scripts).await,
+        Commands::Install { frozen_lockfile, ignore_scripts } => commands::install(frozen_lockfile, ignore_scripts).await,
+        Commands::Ci { ignore_scripts } => commands::ci(ignore_scripts).await,
+        Commands::Remove { package, dev, ignore_scripts } => commands::remove(&package, dev, ignore_scripts).await,
         Commands::Search { query } => commands::search(&query).await,
         Commands::Init { name } => commands::init(name.as_deref()).await,
-        Commands::Run { script } => commands::run_script(&script).await,
-        Commands::Update { package } => commands::update(package.as_deref()).await,
+        Commands::Run { script, args } => commands::run_script(script.as_deref(), &args).await,
+        Commands::Exec { bin, args } => commands::exec(&bin, &args).await,
+        Commands::Dlx { package, args } => commands::dlx(&package, &args).await,
+        Commands::Update { package, ignore_scripts } => commands::update(package.as_deref(), ignore_scripts).await,
         Commands::List => commands::list().await,
         Commands::Cache { cmd } => match cmd {
             CacheCmd::Clean { days } => cache::clean_cache(days),
@@ -106,6 +272,11 @@ async fn main() {
 
     // Handle errors
     if let Err(e) = result {
+        // A failed script decides the exit code, like it would in npm
+        if let Some(failed) = e.downcast_ref::<scripts::ScriptFailed>() {
+            eprintln!("{}", format!("❌ {}", failed).red());
+            std::process::exit(failed.code);
+        }
         if verbose {
             eprintln!("{}", format!("❌ Error: {:#?}", e).red());
         } else {
@@ -1,35 +1,73 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::Path;
 use anyhow::Result;
 
+// The project manifest, in the standard package.json format. Fields `a`
+// doesn't use are kept in `other` so they survive a rewrite.
 #[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Manifest {
     pub name: String,
     pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
-    pub author: Option<String>,
-    pub dependencies: HashMap<String, String>,
-    pub dev_dependencies: HashMap<String, String>,
-    pub scripts: HashMap<String, String>,
+    // A string or a { name, email, url } object
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    // Older a.json files used snake_case
+    #[serde(rename = "devDependencies", alias = "dev_dependencies", skip_serializing_if = "BTreeMap::is_empty")]
+    pub dev_dependencies: BTreeMap<String, String>,
+    // Installed when possible; failures and other platforms are skipped
+    #[serde(rename = "optionalDependencies", skip_serializing_if = "BTreeMap::is_empty")]
+    pub optional_dependencies: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub scripts: BTreeMap<String, String>,
+    // Dependencies allowed to run install scripts
+    #[serde(rename = "trustedDependencies", skip_serializing_if = "Vec::is_empty")]
+    pub trusted_dependencies: Vec<String>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+// package.json, unless the project only has the a.json of earlier versions
+pub fn manifest_file() -> &'static str {
+    manifest_file_in(Path::new("."))
+}
+
+// The manifest file name of the package in `dir`
+pub fn manifest_file_in(dir: &Path) -> &'static str {
+    if !dir.join("package.json").exists() && dir.join("a.json").exists() {
+        "a.json"
+    } else {
+        "package.json"
+    }
 }
 
 pub fn read_manifest() -> Result<Manifest> {
-    let manifest_path = Path::new("a.json");
+    let manifest_path = Path::new(manifest_file());
     
     if !manifest_path.exists() {
         return Ok(Manifest::default());
     }
     
     let content = fs::read_to_string(manifest_path)?;
-    let manifest: Manifest = serde_json::from_str(&content)?;
-    Ok(manifest)
+    parse_manifest(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", manifest_path.display(), e))
+}
+
+pub fn parse_manifest(content: &str) -> Result<Manifest> {
+    Ok(serde_json::from_str(content)?)
 }
 
 pub fn write_manifest(manifest: &Manifest) -> Result<()> {
-    let content = serde_json::to_string_pretty(manifest)?;
-    fs::write("a.json", content)?;
+    let mut content = serde_json::to_string_pretty(manifest)?;
+    content.push('\n');
+    fs::write(manifest_file(), content)?;
     Ok(())
 }
 
@@ -40,34 +78,302 @@ pub fn init(name: &str) -> Result<()> {
         version: "0.1.0".to_string(),
         description: Some("Package created with a package manager".to_string()),
         author: None,
-        dependencies: HashMap::new(),
-        dev_dependencies: HashMap::new(),
-        scripts: HashMap::new(),
+        ..Default::default()
     };
     
     write_manifest(&manifest)
 }
 
 pub fn add_dependency(pkg: &str, version: &str, is_dev: bool) -> Result<()> {
-    let mut manifest = read_manifest()?;
-    
-    if is_dev {
-        manifest.dev_dependencies.insert(pkg.to_string(), version.to_string());
-    } else {
-        manifest.dependencies.insert(pkg.to_string(), version.to_string());
-    }
-    
-    write_manifest(&manifest)
+    edit_manifest(|doc| doc.set_dependency(section(is_dev), pkg, version))
 }
 
 pub fn remove_dependency(pkg: &str, is_dev: bool) -> Result<()> {
-    let mut manifest = read_manifest()?;
-    
-    if is_dev {
-        manifest.dev_dependencies.remove(pkg);
-    } else {
-        manifest.dependencies.remove(pkg);
+    edit_manifest(|doc| {
+        doc.remove_dependency(section(is_dev), pkg);
+    })
+}
+
+// Change the range of a dependency in every section that lists it
+pub fn update_dependency(pkg: &str, range: &str) -> Result<()> {
+    edit_manifest(|doc| {
+        for section in ["dependencies", "devDependencies", "optionalDependencies"] {
+            if doc.has_dependency(section, pkg) {
+                doc.set_dependency(section, pkg, range);
+            }
+        }
+    })
+}
+
+fn section(is_dev: bool) -> &'static str {
+    if is_dev { "devDependencies" } else { "dependencies" }
+}
+
+// Apply an edit to the manifest on disk, changing nothing else in the file
+fn edit_manifest(edit: impl FnOnce(&mut ManifestDocument)) -> Result<()> {
+    let path = manifest_file();
+    let mut doc = match fs::read_to_string(path) {
+        Ok(content) => ManifestDocument::parse(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path, e))?,
+        Err(_) => ManifestDocument::parse("{}\n")?,
+    };
+    edit(&mut doc);
+    fs::write(path, doc.render()?)?;
+    Ok(())
+}
+
+// The manifest as the user wrote it. Only the dependency sections that were
+// edited are written again; everything else keeps its original text, and new
+// sections use the indentation and line endings of the file.
+pub struct ManifestDocument {
+    content: String,
+    root: serde_json::Map<String, Value>,
+    // Sections whose text has to be rewritten
+    changed: BTreeSet<String>,
+    indent: String,
+    crlf: bool,
+}
+
+impl ManifestDocument {
+    pub fn parse(content: &str) -> Result<ManifestDocument> {
+        let root = match serde_json::from_str(content)? {
+            Value::Object(root) => root,
+            _ => anyhow::bail!("expected a JSON object"),
+        };
+        let mut changed = BTreeSet::new();
+        if root.contains_key("dev_dependencies") {
+            changed.insert("devDependencies".to_string());
+        }
+        Ok(ManifestDocument {
+            content: content.to_string(),
+            root: migrate_dev_dependencies(root),
+            changed,
+            indent: detect_indent(content),
+            crlf: content.contains("\r\n"),
+        })
     }
-    
-    write_manifest(&manifest)
+
+    // Add or update a dependency. A new section is added at the end; the
+    // section that changed is kept sorted, like npm does.
+    pub fn set_dependency(&mut self, section: &str, pkg: &str, version: &str) {
+        let entry = self.root.entry(section.to_string())
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+        if !entry.is_object() {
+            *entry = Value::Object(serde_json::Map::new());
+        }
+        if let Value::Object(deps) = entry {
+            deps.insert(pkg.to_string(), Value::String(version.to_string()));
+            deps.sort_keys();
+        }
+        self.changed.insert(section.to_string());
+    }
+
+    pub fn has_dependency(&self, section: &str, pkg: &str) -> bool {
+        matches!(self.root.get(section), Some(Value::Object(deps)) if deps.contains_key(pkg))
+    }
+
+    // Returns whether the dependency was there
+    pub fn remove_dependency(&mut self, section: &str, pkg: &str) -> bool {
+        let removed = match self.root.get_mut(section) {
+            Some(Value::Object(deps)) => deps.shift_remove(pkg).is_some(),
+            _ => false,
+        };
+        if removed {
+            self.changed.insert(section.to_string());
+        }
+        removed
+    }
+
+    pub fn render(&self) -> Result<String> {
+        let (open, close, members) = top_level_members(&self.content)?;
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        let has_standard_dev = members.iter().any(|m| m.key == "devDependencies");
+
+        // (start, end, replacement) for spans of the original, in order
+        let mut edits: Vec<(usize, usize, String)> = Vec::new();
+        for (i, member) in members.iter().enumerate() {
+            if member.key == "dev_dependencies" {
+                if has_standard_dev {
+                    // Merged into devDependencies, so the legacy member goes
+                    let (start, end) = match i {
+                        0 => (member.key_start, members.get(1).map_or(close, |next| next.key_start)),
+                        _ => (members[i - 1].value_end, member.value_end),
+                    };
+                    edits.push((start, end, String::new()));
+                } else {
+                    let value = self.render_section("devDependencies")?;
+                    edits.push((member.key_start, member.value_end, format!("\"devDependencies\": {}", value)));
+                }
+            } else if self.changed.contains(&member.key) {
+                edits.push((member.value_start, member.value_end, self.render_section(&member.key)?));
+            }
+        }
+
+        let mut added = String::new();
+        for key in self.root.keys() {
+            let present = members.iter().any(|m| &m.key == key || (m.key == "dev_dependencies" && key == "devDependencies"));
+            if !present {
+                if !added.is_empty() || !members.is_empty() {
+                    added.push(',');
+                }
+                added.push_str(&format!("{}{}{}: {}", newline, self.indent, serde_json::to_string(key)?, self.render_section(key)?));
+            }
+        }
+        if !added.is_empty() {
+            match members.last() {
+                Some(last) => edits.push((last.value_end, last.value_end, added)),
+                None => edits.push((open + 1, close, format!("{}{}", added, newline))),
+            }
+        }
+
+        let mut content = String::new();
+        let mut at = 0;
+        for (start, end, replacement) in edits {
+            content.push_str(&self.content[at..start]);
+            content.push_str(&replacement);
+            at = end;
+        }
+        content.push_str(&self.content[at..]);
+        Ok(content)
+    }
+
+    // A top-level value, pretty-printed one level deep
+    fn render_section(&self, key: &str) -> Result<String> {
+        let mut buf = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(self.indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+        self.root.get(key).unwrap_or(&Value::Null).serialize(&mut serializer)?;
+
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        Ok(String::from_utf8(buf)?.replace('\n', &format!("{}{}", newline, self.indent)))
+    }
+}
+
+// A member of the top-level object, with byte offsets into the text
+struct Member {
+    key: String,
+    key_start: usize,
+    value_start: usize,
+    value_end: usize,
+}
+
+// The offsets of the outer braces and the members of a JSON object that
+// serde_json already accepted
+fn top_level_members(content: &str) -> Result<(usize, usize, Vec<Member>)> {
+    let bytes = content.as_bytes();
+    let skip_space = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+    let malformed = || anyhow::anyhow!("expected a JSON object");
+
+    let open = skip_space(0);
+    if bytes.get(open) != Some(&b'{') {
+        return Err(malformed());
+    }
+    let mut members = Vec::new();
+    let mut i = skip_space(open + 1);
+    loop {
+        match bytes.get(i) {
+            Some(b'}') => return Ok((open, i, members)),
+            Some(b'"') => {}
+            _ => return Err(malformed()),
+        }
+        let key_start = i;
+        let key_end = skip_value(bytes, i);
+        let key: String = serde_json::from_str(&content[key_start..key_end])?;
+        i = skip_space(key_end);
+        if bytes.get(i) != Some(&b':') {
+            return Err(malformed());
+        }
+        let value_start = skip_space(i + 1);
+        let value_end = skip_value(bytes, value_start);
+        members.push(Member { key, key_start, value_start, value_end });
+
+        i = skip_space(value_end);
+        if bytes.get(i) == Some(&b',') {
+            i = skip_space(i + 1);
+        }
+    }
+}
+
+// The end of the JSON value starting at `start`
+fn skip_value(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut i = start;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        i += 1;
+        if in_string {
+            match byte {
+                b'\\' => i += 1,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return i;
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            b',' | b' ' | b'\t' | b'\r' | b'\n' if depth == 0 => return i - 1,
+            _ => {}
+        }
+    }
+    i
+}
+
+// Older a.json files call the section `dev_dependencies`. It is renamed in
+// place, so edits of dev dependencies and the next read agree on one key.
+fn migrate_dev_dependencies(root: serde_json::Map<String, Value>) -> serde_json::Map<String, Value> {
+    if !root.contains_key("dev_dependencies") {
+        return root;
+    }
+
+    let mut migrated = serde_json::Map::new();
+    for (key, value) in root {
+        let legacy = key == "dev_dependencies";
+        let key = if legacy { "devDependencies".to_string() } else { key };
+        match (migrated.get_mut(&key), value) {
+            // Both spellings present: merge them, the standard one wins
+            (Some(Value::Object(existing)), Value::Object(other)) => {
+                for (pkg, range) in other {
+                    if legacy {
+                        existing.entry(pkg).or_insert(range);
+                    } else {
+                        existing.insert(pkg, range);
+                    }
+                }
+            }
+            (Some(_), _) => {}
+            (None, value) => {
+                migrated.insert(key, value);
+            }
+        }
+    }
+    migrated
+}
+
+// Indentation of the first indented line: tabs or any number of spaces.
+// Defaults to two spaces, like npm.
+fn detect_indent(content: &str) -> String {
+    content.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ")
+        .to_string()
 }
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, ACCEPT, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::cache::NotCached;
+use crate::config::NetworkMode;
+use crate::http::HttpResponse;
+
+// On-disk cache of registry documents. Entries are keyed by URL and the
+// requested format, reused as-is while younger than the max age and
+// revalidated with the registry's ETag / Last-Modified after that.
+
+// npm's abbreviated ("corgi") packument: only what an install needs
+pub const ABBREVIATED: &str = "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
+
+pub struct MetadataCache {
+    pub dir: PathBuf,
+    pub max_age: Duration,
+    pub mode: NetworkMode,
+}
+
+// Stored next to each cached document
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, rename = "lastModified", skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    // Seconds since the epoch of the last fetch or revalidation
+    #[serde(rename = "fetchedAt")]
+    fetched_at: u64,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Validators {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        Validators { etag: header(ETAG), last_modified: header(LAST_MODIFIED), fetched_at: now() }
+    }
+
+    fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.fetched_at))
+    }
+}
+
+impl MetadataCache {
+    pub fn from_config() -> MetadataCache {
+        let config = crate::config::get();
+        MetadataCache {
+            dir: config.cache_dir.join("metadata"),
+            max_age: config.metadata_max_age,
+            mode: config.network,
+        }
+    }
+
+    // GET `url` accepting `accept`, going through the cache. A 304 from the
+    // registry is answered with the cached body as a 200.
+    pub async fn get(&self, url: &str, accept: &str, build: impl Fn(&Client) -> RequestBuilder) -> Result<HttpResponse> {
+        let (body_path, meta_path) = self.paths(url, accept);
+        let cached = self.read(&body_path, &meta_path);
+
+        if let Some((validators, body)) = &cached {
+            if validators.age() < self.max_age || self.mode != NetworkMode::Online {
+                return Ok(ok_response(body.clone()));
+            }
+        }
+        if self.mode == NetworkMode::Offline {
+            return Err(NotCached(url.to_string()).into());
+        }
+
+        let resp = crate::http::get(|client| {
+            let mut request = build(client).header(ACCEPT, accept);
+            if let Some((validators, _)) = &cached {
+                if let Some(etag) = &validators.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &validators.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            request
+        }).await?;
+
+        if resp.status == StatusCode::NOT_MODIFIED {
+            if let Some((mut validators, body)) = cached {
+                validators.fetched_at = now();
+                let _ = write_atomic(&meta_path, &serde_json::to_vec(&validators)?);
+                return Ok(ok_response(body));
+            }
+        }
+
+        if resp.status.is_success() {
+            // A failed cache write only costs a refetch next time
+            let validators = Validators::from_headers(&resp.headers);
+            let _ = write_atomic(&body_path, &resp.body)
+                .and_then(|_| write_atomic(&meta_path, &serde_json::to_vec(&validators)?));
+        }
+
+        Ok(resp)
+    }
+
+    fn paths(&self, url: &str, accept: &str) -> (PathBuf, PathBuf) {
+        let digest = Sha256::digest(format!("{}\n{}", accept, url).as_bytes());
+        let key: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        (self.dir.join(format!("{}.json", key)), self.dir.join(format!("{}.meta", key)))
+    }
+
+    fn read(&self, body_path: &Path, meta_path: &Path) -> Option<(Validators, Vec<u8>)> {
+        let validators = serde_json::from_slice(&fs::read(meta_path).ok()?).ok()?;
+        let body = fs::read(body_path).ok()?;
+        Some((validators, body))
+    }
+}
+
+fn ok_response(body: Vec<u8>) -> HttpResponse {
+    HttpResponse { status: StatusCode::OK, headers: HeaderMap::new(), body }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Write through a temporary file so readers never see a partial entry
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use dirs::home_dir;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Support for npm's `.npmrc` files: the default registry, per-scope
+// registries and credentials keyed by "nerf dart" (`//host/path/:key`).
+
+#[derive(Debug, Default, Clone)]
+pub struct Npmrc {
+    pub registry: Option<String>,
+    // "@scope" -> registry URL
+    pub scopes: HashMap<String, String>,
+    // Nerf dart ("//host/path/") -> credential settings for that registry
+    pub credentials: HashMap<String, Credentials>,
+    pub always_auth: bool,
+    // Network settings, in npm's units (milliseconds for the timeout)
+    pub fetch_timeout: Option<u64>,
+    pub fetch_retries: Option<u32>,
+    // "hoisted" or "isolated", like pnpm's setting of the same name
+    pub node_linker: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    pub token: Option<String>,
+    // Base64 "user:password", as written by `npm login` for basic auth
+    pub auth: Option<String>,
+    pub username: Option<String>,
+    // Base64 encoded password
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    // Value for the Authorization header, if these credentials are complete
+    pub fn header(&self) -> Option<String> {
+        if let Some(token) = &self.token {
+            return Some(format!("Bearer {}", token));
+        }
+        if let Some(auth) = &self.auth {
+            return Some(format!("Basic {}", auth));
+        }
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            let password = STANDARD.decode(password).ok()
+                .and_then(|p| String::from_utf8(p).ok())?;
+            return Some(format!("Basic {}", STANDARD.encode(format!("{}:{}", username, password))));
+        }
+        None
+    }
+}
+
+impl Npmrc {
+    // Read the global, user and project files; later files take precedence
+    pub fn load() -> Result<Npmrc> {
+        let mut npmrc = Npmrc::default();
+        for path in config_files() {
+            if let Ok(content) = fs::read_to_string(&path) {
+                npmrc.merge(&content, |key| std::env::var(key).ok())
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(npmrc)
+    }
+
+    // Apply the settings from one file on top of what is already loaded.
+    // `${VAR}` references are expanded through `var`.
+    pub fn merge(&mut self, content: &str, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') || line.starts_with('[') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let key = interpolate(key.trim(), &var)?;
+            let value = interpolate(unquote(value.trim()), &var)?;
+
+            if let Some((nerf_dart, setting)) = key.rsplit_once(":_") {
+                if nerf_dart.starts_with("//") {
+                    let credentials = self.credentials.entry(normalize_nerf_dart(nerf_dart)).or_default();
+                    match setting {
+                        "authToken" => credentials.token = Some(value),
+                        "auth" => credentials.auth = Some(value),
+                        "password" => credentials.password = Some(value),
+                        _ => {}
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(nerf_dart) = key.strip_suffix(":username").filter(|k| k.starts_with("//")) {
+                self.credentials.entry(normalize_nerf_dart(nerf_dart)).or_default().username = Some(value);
+                continue;
+            }
+
+            if let Some(scope) = key.strip_suffix(":registry").filter(|k| k.starts_with('@')) {
+                self.scopes.insert(scope.to_string(), value.trim_end_matches('/').to_string());
+                continue;
+            }
+
+            match key.as_str() {
+                "registry" => self.registry = Some(value.trim_end_matches('/').to_string()),
+                "always-auth" => self.always_auth = value == "true",
+                "fetch-timeout" => self.fetch_timeout = value.parse().ok(),
+                "fetch-retries" => self.fetch_retries = value.parse().ok(),
+                "node-linker" => self.node_linker = Some(value),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Credentials for a request URL: the most specific nerf dart that is a
+    // prefix of the URL wins
+    pub fn credentials_for(&self, url: &str) -> Option<&Credentials> {
+        let mut dart = nerf_dart(url)?;
+        loop {
+            if let Some(credentials) = self.credentials.get(&dart) {
+                if credentials.header().is_some() {
+                    return Some(credentials);
+                }
+            }
+
+            // Drop the last path segment: "//host/a/b/" -> "//host/a/"
+            let trimmed = dart.trim_end_matches('/');
+            match trimmed.rfind('/') {
+                Some(i) if i > 1 => dart = trimmed[..=i].to_string(),
+                _ => return None,
+            }
+        }
+    }
+}
+
+// "https://registry.example.com:8443/npm/pkg" -> "//registry.example.com:8443/npm/pkg/"
+pub fn nerf_dart(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("//")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or(rest);
+    Some(normalize_nerf_dart(&format!("//{}", rest)))
+}
+
+fn normalize_nerf_dart(dart: &str) -> String {
+    format!("{}/", dart.trim_end_matches('/'))
+}
+
+fn unquote(value: &str) -> &str {
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+    if quoted { &value[1..value.len() - 1] } else { value }
+}
+
+// Expand `${VAR}` references from the environment, like npm does. An unset
+// variable is an error rather than an empty value, which would otherwise be
+// sent as a blank token.
+fn interpolate(value: &str, var: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else { break };
+        let name = &rest[start + 2..start + len];
+        result.push_str(&rest[..start]);
+        match var(name) {
+            Some(v) => result.push_str(&v),
+            None => anyhow::bail!("${{{}}} is not set", name),
+        }
+        rest = &rest[start + len + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn config_files() -> Vec<PathBuf> {
+    let global = std::env::var("NPM_CONFIG_GLOBALCONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("/etc/npmrc"));
+    let user = std::env::var("NPM_CONFIG_USERCONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home_dir().unwrap_or_default().join(".npmrc"));
+    let project = Path::new(".npmrc").to_path_buf();
+
+    vec![global, user, project]
+}
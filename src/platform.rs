@@ -0,0 +1,83 @@
+use std::fmt;
+use std::path::Path;
+
+// The `os`, `cpu` and `libc` fields of package.json use Node's names for
+// `process.platform`, `process.arch` and the C library of Linux systems.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub cpu: String,
+    // Only known on Linux: "glibc" or "musl"
+    pub libc: Option<String>,
+}
+
+impl Platform {
+    pub fn current() -> Platform {
+        let os = match std::env::consts::OS {
+            "macos" => "darwin",
+            "windows" => "win32",
+            "solaris" | "illumos" => "sunos",
+            other => other,
+        };
+        let cpu = match std::env::consts::ARCH {
+            "x86_64" => "x64",
+            "x86" => "ia32",
+            "aarch64" => "arm64",
+            "powerpc64" => "ppc64",
+            "loongarch64" => "loong64",
+            other => other,
+        };
+        let libc = (os == "linux").then(|| if is_musl() { "musl" } else { "glibc" }.to_string());
+
+        Platform { os: os.to_string(), cpu: cpu.to_string(), libc }
+    }
+
+    // Whether a package restricted to these values can run here
+    pub fn supports(&self, os: &[String], cpu: &[String], libc: &[String]) -> bool {
+        matches(os, &self.os)
+            && matches(cpu, &self.cpu)
+            && self.libc.as_ref().map(|l| matches(libc, l)).unwrap_or(true)
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.os, self.cpu)?;
+        if let Some(libc) = &self.libc {
+            write!(f, " {}", libc)?;
+        }
+        Ok(())
+    }
+}
+
+// npm's rules: an empty list allows everything, "!value" entries block a
+// value, and any other entries form an allow list
+fn matches(list: &[String], value: &str) -> bool {
+    if list.is_empty() {
+        return true;
+    }
+
+    let mut allowed = None;
+    for entry in list {
+        match entry.strip_prefix('!') {
+            Some(blocked) if blocked == value => return false,
+            Some(_) => {}
+            None if entry == value || entry == "any" => allowed = Some(true),
+            None => {
+                allowed.get_or_insert(false);
+            }
+        }
+    }
+    allowed.unwrap_or(true)
+}
+
+// musl systems ship their dynamic loader as /lib/ld-musl-<arch>.so.1
+fn is_musl() -> bool {
+    if cfg!(target_env = "musl") {
+        return true;
+    }
+    std::fs::read_dir(Path::new("/lib"))
+        .map(|entries| entries.flatten().any(|e| e.file_name().to_string_lossy().starts_with("ld-musl-")))
+        .unwrap_or(false)
+}
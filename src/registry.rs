@@ -1,12 +1,13 @@
-use reqwest::Client;
-use serde::Deserialize;
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::Cursor;
-use tar::Archive;
-use flate2::read::GzDecoder;
+use std::path::Path;
 use colored::Colorize;
+use crate::integrity::Integrity;
+use crate::metadata::{self, MetadataCache};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct NpmDist {
     pub tarball: String,
     #[serde(default)]
@@ -15,10 +16,34 @@ pub struct NpmDist {
     pub integrity: Option<String>,
 }
 
+// Extra information about a peer dependency
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PeerDependencyMeta {
+    #[serde(default)]
+    pub optional: bool,
+}
+
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 pub struct NpmVersion {
     pub dist: NpmDist,
     #[serde(default)]
+    pub dependencies: std::collections::HashMap<String, String>,
+    #[serde(default, rename = "peerDependencies")]
+    pub peer_dependencies: std::collections::BTreeMap<String, String>,
+    #[serde(default, rename = "peerDependenciesMeta")]
+    pub peer_dependencies_meta: std::collections::BTreeMap<String, PeerDependencyMeta>,
+    // Also listed in `dependencies` by the registry
+    #[serde(default, rename = "optionalDependencies")]
+    pub optional_dependencies: std::collections::HashMap<String, String>,
+    // Platforms the package supports, see platform.rs
+    #[serde(default)]
+    pub os: Vec<String>,
+    #[serde(default)]
+    pub cpu: Vec<String>,
+    #[serde(default)]
+    pub libc: Vec<String>,
+    #[serde(default)]
     pub description: Option<String>,
     #[serde(default)]
     pub license: Option<String>,
@@ -27,6 +52,7 @@ pub struct NpmVersion {
 }
 
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 pub struct NpmResponse {
     #[serde(rename = "dist-tags")]
     pub dist_tags: std::collections::HashMap<String, String>,
@@ -37,133 +63,142 @@ pub struct NpmResponse {
     pub description: Option<String>,
 }
 
-pub async fn get_latest_version(pkg: &str) -> anyhow::Result<String> {
-    let url = format!("https://registry.npmjs.org/{}", pkg);
-    
-    // Handle large response with a timeout and retries
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
+// Registry URL of a package's document. Scoped names keep the `@` but have
+// their slash escaped, the way the npm registry expects.
+pub fn packument_url(pkg: &str) -> String {
+    format!("{}/{}", crate::config::get().registry_for(pkg), pkg.replace('/', "%2f"))
+}
+
+// Start a GET request made on behalf of `pkg`, with the credentials .npmrc
+// configures for the URL or the package's registry
+fn authorized_get(client: &Client, url: &str, pkg: &str) -> RequestBuilder {
+    let config = crate::config::get();
+    let request = client.get(url);
+    match config.auth_header(url, config.registry_for(pkg)) {
+        Some(header) => request.header(AUTHORIZATION, header),
+        None => request,
+    }
+}
+
+// Fetch the registry document (packument) for a package. The abbreviated
+// form is enough for resolving and installing, and is served from the
+// metadata cache when it is fresh.
+pub async fn fetch_packument(pkg: &str) -> anyhow::Result<NpmResponse> {
+    let url = packument_url(pkg);
+    let resp = MetadataCache::from_config()
+        .get(&url, metadata::ABBREVIATED, |client| authorized_get(client, &url, pkg))
+        .await?;
+    
+    if !resp.status.is_success() {
+        return Err(anyhow::anyhow!("Registry request for {} failed with status: {}", pkg, resp.status));
+    }
     
-    let resp: NpmResponse = client.get(url)
-        .send().await?
-        .json().await
-        .map_err(|e| anyhow::anyhow!("Failed to parse registry response: {}", e))?;
+    serde_json::from_slice(&resp.body)
+        .map_err(|e| anyhow::anyhow!("Failed to parse registry response for {}: {}", pkg, e))
+}
+
+pub async fn get_latest_version(pkg: &str) -> anyhow::Result<String> {
+    let resp = fetch_packument(pkg).await?;
     
     // Get the latest version from dist_tags, or return an error if "latest" not found
     resp.dist_tags.get("latest")
         .ok_or_else(|| anyhow::anyhow!("No 'latest' version found for package {}", pkg))
-        .map(|v| v.clone())
+        .cloned()
 }
 
-pub async fn download_and_unpack(pkg: &str, version: &str) -> anyhow::Result<()> {
-    // Form URLs and paths
-    let url = format!("https://registry.npmjs.org/{}", pkg);
-    let cache_dir = crate::cache::get_cache_dir();
-    let package_cache = cache_dir.join("packages");
-    let tarball_path = package_cache.join(format!("{}-{}.tgz", pkg.replace("/", "-"), version));
-    
-    // Create directory structure if it doesn't exist
-    fs::create_dir_all(&package_cache)?;
-    fs::create_dir_all("node_modules")?;
-    
-    // First check if we have a cached version
-    let tarball_bytes = if tarball_path.exists() {
-        println!("{}", format!("📦 Using cached version of {}@{}", pkg, version).blue());
-        fs::read(&tarball_path)?
-    } else {
-        // If not in cache, download it
-        println!("{}", format!("⬇️ Downloading {}@{}", pkg, version).yellow());
-        
-        // First get the full package info to find the tarball URL
-        let resp: NpmResponse = Client::new().get(&url)
-            .send().await?
-            .json().await?;
-        
-        // Get the version data, or return an error if version not found
-        let version_data = resp.versions.get(version)
-            .ok_or_else(|| anyhow::anyhow!("Version {} not found for package {}", version, pkg))?;
+// Install pkg@version into `pkg_path`. When the tarball details are already
+// known (e.g. from the lockfile) the registry document is never fetched.
+pub async fn install_package(pkg: &str, version: &str, dist: Option<&NpmDist>, pkg_path: &Path) -> anyhow::Result<()> {
+    let tarball_bytes = fetch_tarball(pkg, version, dist).await?;
+    unpack_tarball(pkg, version, tarball_bytes, pkg_path)
+}
+
+// Get the tarball for pkg@version, from the cache if possible. Cached and
+// downloaded tarballs are both checked against the registry's integrity hash.
+pub async fn fetch_tarball(pkg: &str, version: &str, dist: Option<&NpmDist>) -> anyhow::Result<Vec<u8>> {
+    let tarball_path = crate::cache::tarball_path(pkg, version);
+    
+    // Without lockfile details, look the tarball up in the registry
+    let looked_up;
+    let dist = match dist {
+        Some(dist) => dist,
+        None => {
+            let mut resp = fetch_packument(pkg).await?;
             
-        let tarball_url = &version_data.dist.tarball;
-        let bytes = Client::new().get(tarball_url)
-            .send().await?
-            .bytes().await?;
-        
-        // Save to cache
-        fs::write(&tarball_path, &bytes)?;
-        bytes.to_vec()
+            // Get the version data, or return an error if version not found
+            looked_up = resp.versions.remove(version)
+                .ok_or_else(|| anyhow::anyhow!("Version {} not found for package {}", version, pkg))?;
+            &looked_up.dist
+        }
     };
+    let expected = Integrity::for_dist(dist)?;
+    
+    // First check if we have a cached version
+    if crate::cache::is_package_cached(pkg, version) {
+        if let Some(bytes) = crate::cache::read_cached_tarball(&tarball_path, expected.as_ref()) {
+            println!("{}", format!("📦 Using cached version of {}@{}", pkg, version).blue());
+            return Ok(bytes);
+        }
+    }
+    
+    if crate::config::get().network == crate::config::NetworkMode::Offline {
+        return Err(crate::cache::NotCached(format!("{}@{}", pkg, version)).into());
+    }
+    
+    // If not in cache, download it
+    println!("{}", format!("⬇️ Downloading {}@{}", pkg, version).yellow());
+    
+    let resp = crate::http::get(|client| authorized_get(client, &dist.tarball, pkg)).await?;
+    if !resp.status.is_success() {
+        return Err(anyhow::anyhow!("Download of {}@{} failed with status: {}", pkg, version, resp.status));
+    }
+    let bytes = resp.body;
     
-    // Create a properly normalized package path
-    let pkg_path = std::path::Path::new("node_modules").join(pkg);
-    let parent_dir = pkg_path.parent().unwrap_or_else(|| std::path::Path::new("node_modules"));
+    match &expected {
+        Some(expected) => expected.check(&bytes)
+            .map_err(|e| anyhow::anyhow!("Integrity check failed for {}@{}: {}", pkg, version, e))?,
+        None => println!("{}", format!("⚠️ No integrity information for {}@{}, skipping verification", 
+                         pkg, version).yellow()),
+    }
+    
+    // Save to cache
+    crate::cache::write_cached_tarball(&tarball_path, &bytes)?;
+    Ok(bytes)
+}
+
+// Extract a package tarball into `pkg_path`, replacing anything already there
+pub fn unpack_tarball(pkg: &str, version: &str, tarball_bytes: Vec<u8>, pkg_path: &Path) -> anyhow::Result<()> {
+    let parent_dir = pkg_path.parent().unwrap_or_else(|| Path::new("node_modules"));
     
     // Make sure the parent directory exists
     fs::create_dir_all(parent_dir)?;
     
-    // Remove existing package directory if it exists to avoid conflicts
-    if pkg_path.exists() {
-        // On Windows, attempting to remove a directory that's in use can fail
-        // We'll attempt it and continue if it succeeds
-        let _ = fs::remove_dir_all(&pkg_path);
+    // Remove a previous install to avoid conflicts. Its node_modules folder
+    // holds nested dependencies, which are installed separately (possibly at
+    // the same time), so it is left alone.
+    if let Ok(entries) = fs::read_dir(pkg_path) {
+        for entry in entries.flatten() {
+            if entry.file_name() == "node_modules" {
+                continue;
+            }
+            // On Windows, attempting to remove a directory that's in use can fail
+            // We'll attempt it and continue if it succeeds
+            let path = entry.path();
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            let _ = if is_dir { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        }
     }
     
     // Create the package directory
-    fs::create_dir_all(&pkg_path)?;
+    fs::create_dir_all(pkg_path)?;
     
     // Unpack the tarball
     println!("{}", format!("📦 Unpacking {}@{}", pkg, version).green());
-    let gz = GzDecoder::new(Cursor::new(tarball_bytes));
-    let mut archive = Archive::new(gz);
+    let report = crate::extract::extract_package(&tarball_bytes, pkg_path)?;
     
-    // Some packages use different folder structures, so we need to handle multiple cases
-    let common_prefixes = ["package/", "package", "/"];
-    
-    // Get the entries to process
-    let entries = match archive.entries() {
-        Ok(entries) => entries,
-        Err(e) => return Err(anyhow::anyhow!("Failed to read tarball entries: {}", e)),
-    };
-    
-    // Process each entry in the tarball
-    for entry in entries {
-        let mut entry = match entry {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("Warning: Failed to read entry in tarball: {}", e);
-                continue;
-            }
-        };
-        
-        let path = match entry.path() {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("Warning: Failed to get path for entry: {}", e);
-                continue;
-            }
-        };
-        
-        // Try to strip common prefixes that npm packages use
-        let rel_path = common_prefixes.iter()
-            .find_map(|prefix| path.strip_prefix(prefix).ok())
-            .unwrap_or(&path);
-            
-        if rel_path.to_string_lossy().is_empty() {
-            continue; // Skip empty paths
-        }
-        
-        let target_path = pkg_path.join(rel_path);
-        
-        // Ensure the parent directory exists
-        if let Some(parent) = target_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        
-        // Unpack the file
-        if let Err(e) = entry.unpack(&target_path) {
-            eprintln!("Warning: Failed to unpack {}: {}", target_path.display(), e);
-            // Continue unpacking other files rather than failing completely
-        }
+    for (path, reason) in &report.rejected {
+        eprintln!("{}", format!("⚠️ Skipped unsafe entry '{}' in {}@{}: {}", 
+                  path, pkg, version, reason).yellow());
     }
     
     Ok(())
@@ -185,21 +220,15 @@ pub struct NpmSearchObj {
 }
 
 pub async fn search_package(query: &str) -> anyhow::Result<Vec<String>> {
-    let url = format!("https://registry.npmjs.org/-/v1/search?text={}", query);
-    
-    // Set a timeout for the search request
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(15))
-        .build()?;
-    
-    let resp = client.get(url).send().await?;
+    let url = format!("{}/-/v1/search?text={}", crate::config::get().registry, query);
+    let resp = crate::http::get(|client| authorized_get(client, &url, "")).await?;
     
     // Check response status
-    if !resp.status().is_success() {
-        return Err(anyhow::anyhow!("Search request failed with status: {}", resp.status()));
+    if !resp.status.is_success() {
+        return Err(anyhow::anyhow!("Search request failed with status: {}", resp.status));
     }
     
-    let search_resp: NpmSearchResponse = resp.json().await
+    let search_resp: NpmSearchResponse = serde_json::from_slice(&resp.body)
         .map_err(|e| anyhow::anyhow!("Failed to parse search results: {}", e))?;
     
     Ok(search_resp.objects.into_iter().map(|o| o.package.name).collect())
@@ -1,43 +1,399 @@
 use anyhow::Result;
-use semver::{Version, VersionReq};
+use colored::*;
+use crate::lockfile;
 use crate::registry;
-use std::collections::HashMap;
-
-pub async fn resolve_version(pkg: &str, req: &str) -> Result<String> {
-    if req == "latest" {
-        return registry::get_latest_version(pkg).await;
-    }
-    
-    let req = VersionReq::parse(req)?;
-    let url = format!("https://registry.npmjs.org/{}", pkg);
-    let resp: registry::NpmResponse = reqwest::Client::new().get(url).send().await?.json().await?;
-    
-    // Find all versions that match the requirement
-    let mut versions = Vec::new();
-    for (ver_str, _) in resp.versions.iter() {
-        if let Ok(version) = Version::parse(ver_str) {
-            if req.matches(&version) {
-                versions.push(version);
+use crate::semver_range;
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::Future;
+
+// A single package@version node in the resolved dependency graph
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: String,
+    pub dist: registry::NpmDist,
+    // Dependency name -> the exact version it was resolved to
+    pub dependencies: BTreeMap<String, String>,
+    // Same for dependencies the package can do without
+    pub optional_dependencies: BTreeMap<String, String>,
+    // Peer name -> the range this package needs from its environment
+    pub peer_dependencies: BTreeMap<String, String>,
+    pub peer_dependencies_meta: BTreeMap<String, registry::PeerDependencyMeta>,
+    // Platforms the package is restricted to; empty means any
+    pub os: Vec<String>,
+    pub cpu: Vec<String>,
+    pub libc: Vec<String>,
+}
+
+impl ResolvedPackage {
+    pub fn is_optional_peer(&self, peer: &str) -> bool {
+        self.peer_dependencies_meta.get(peer).map(|meta| meta.optional).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ResolvedGraph {
+    // Top-level dependency name -> resolved version
+    pub roots: BTreeMap<String, String>,
+    // Roots that came from optionalDependencies
+    pub optional_roots: BTreeSet<String>,
+    // Every node reachable from the roots, keyed by "name@version"
+    pub packages: HashMap<String, ResolvedPackage>,
+    // Node keys in the order they were discovered (breadth first)
+    pub order: Vec<String>,
+}
+
+pub fn node_key(name: &str, version: &str) -> String {
+    format!("{}@{}", name, version)
+}
+
+// Pick the version of `pkg` to use for `req`. A preferred version (e.g. from
+// the lockfile) wins if it satisfies the range, then any version already in
+// the graph, and finally the highest matching version from the registry.
+fn select_version(
+    pkg: &str,
+    resp: &registry::NpmResponse,
+    req: &str,
+    preferred: Option<&String>,
+    existing: &[String],
+) -> Result<String> {
+    // Dist-tags like "latest" or "next" point straight at a version
+    if let Some(tagged) = resp.dist_tags.get(req) {
+        return Ok(tagged.clone());
+    }
+
+    let range = semver_range::Range::parse(req)
+        .map_err(|e| anyhow::anyhow!("{} for {}", e, pkg))?;
+
+    if let Some(version) = preferred.filter(|v| resp.versions.contains_key(*v) && range.satisfies_str(v)) {
+        return Ok(version.clone());
+    }
+
+    if let Some(version) = semver_range::max_satisfying(existing, &range) {
+        return Ok(version.to_string());
+    }
+
+    semver_range::max_satisfying(resp.versions.keys(), &range)
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("No version of {} found that matches requirement: {}", pkg, req))
+}
+
+// Where a dependency request came from
+enum Edge {
+    Root,
+    // Regular dependency of the node with this key
+    Dependency(String),
+    // Optional dependency of the node with this key
+    OptionalDependency(String),
+    // A peer nothing else in the tree provides, installed on its own
+    Peer,
+}
+
+struct Request {
+    edge: Edge,
+    name: String,
+    range: String,
+    // Failing to resolve an optional dependency only skips it. Everything
+    // below an optional edge is optional too.
+    optional: bool,
+}
+
+// Walk the full dependency graph starting from the manifest's dependencies,
+// fetching each package's registry document once and picking a version for
+// every range encountered along the way. Like npm 7+, required peers that
+// nothing in the tree provides are installed too. Optional dependencies are
+// resolved for every platform so the lockfile works everywhere.
+pub async fn resolve_dependencies(
+    deps: &BTreeMap<String, String>,
+    optional_deps: &BTreeMap<String, String>,
+    preferred: &HashMap<String, String>,
+) -> Result<ResolvedGraph> {
+    resolve_with(deps, optional_deps, preferred, |name| async move { registry::fetch_packument(&name).await }).await
+}
+
+// `resolve_dependencies` with the registry documents coming from `fetch`
+pub async fn resolve_with<F, Fut>(
+    deps: &BTreeMap<String, String>,
+    optional_deps: &BTreeMap<String, String>,
+    preferred: &HashMap<String, String>,
+    fetch: F,
+) -> Result<ResolvedGraph>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<registry::NpmResponse>>,
+{
+    let mut graph = ResolvedGraph::default();
+    let mut packuments: HashMap<String, registry::NpmResponse> = HashMap::new();
+    let mut resolved_versions: HashMap<String, Vec<String>> = HashMap::new();
+    // Packages offline mode couldn't find; the rest of the graph is still
+    // walked so they can all be reported at once
+    let mut uncached: BTreeSet<String> = BTreeSet::new();
+    // Packages whose registry document could not be fetched, with the reason
+    let mut failed: HashMap<String, String> = HashMap::new();
+
+    let mut queue: Vec<Request> = deps.iter()
+        .map(|(name, range)| (name, range, false))
+        .chain(optional_deps.iter().map(|(name, range)| (name, range, true)))
+        .map(|(name, range, optional)| Request {
+            edge: Edge::Root,
+            name: name.clone(),
+            range: range.clone(),
+            optional,
+        })
+        .collect();
+
+    loop {
+        while !queue.is_empty() {
+            let names = queue.iter().map(|request| request.name.clone()).collect();
+            fetch_packuments(&fetch, names, &mut packuments, &mut uncached, &mut failed).await;
+
+            let mut next = Vec::new();
+            for Request { edge, name, range, optional } in queue {
+                let selected = match packuments.get(&name) {
+                    Some(resp) => {
+                        let existing = resolved_versions.get(&name).map(Vec::as_slice).unwrap_or(&[]);
+                        select_version(&name, resp, &range, preferred.get(&name), existing)
+                            .map(|version| (resp, version))
+                    }
+                    None => match failed.get(&name) {
+                        Some(reason) => Err(anyhow::anyhow!("{}", reason)),
+                        None => continue, // Not cached, reported at the end
+                    },
+                };
+                let (resp, version) = match selected {
+                    Ok(selected) => selected,
+                    Err(e) if optional => {
+                        println!("{}", format!("⚠️ Skipping optional dependency {}@{}: {}", name, range, e).yellow());
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+
+                match &edge {
+                    Edge::Root => {
+                        graph.roots.insert(name.clone(), version.clone());
+                        if optional {
+                            graph.optional_roots.insert(name.clone());
+                        }
+                    }
+                    Edge::Dependency(key) => {
+                        if let Some(node) = graph.packages.get_mut(key) {
+                            node.dependencies.insert(name.clone(), version.clone());
+                        }
+                    }
+                    Edge::OptionalDependency(key) => {
+                        if let Some(node) = graph.packages.get_mut(key) {
+                            node.optional_dependencies.insert(name.clone(), version.clone());
+                        }
+                    }
+                    Edge::Peer => {}
+                }
+
+                let key = node_key(&name, &version);
+                if graph.packages.contains_key(&key) {
+                    continue;
+                }
+
+                let version_data = resp.versions.get(&version)
+                    .ok_or_else(|| anyhow::anyhow!("Version {} not found for package {}", version, name))?;
+
+                for (dep, dep_range) in &version_data.dependencies {
+                    if !version_data.optional_dependencies.contains_key(dep) {
+                        next.push(Request {
+                            edge: Edge::Dependency(key.clone()),
+                            name: dep.clone(),
+                            range: dep_range.clone(),
+                            optional,
+                        });
+                    }
+                }
+                for (dep, dep_range) in &version_data.optional_dependencies {
+                    next.push(Request {
+                        edge: Edge::OptionalDependency(key.clone()),
+                        name: dep.clone(),
+                        range: dep_range.clone(),
+                        optional: true,
+                    });
+                }
+
+                resolved_versions.entry(name.clone()).or_default().push(version.clone());
+                graph.order.push(key.clone());
+                graph.packages.insert(key, ResolvedPackage {
+                    name,
+                    version,
+                    dist: version_data.dist.clone(),
+                    dependencies: BTreeMap::new(),
+                    optional_dependencies: BTreeMap::new(),
+                    peer_dependencies: version_data.peer_dependencies.clone(),
+                    peer_dependencies_meta: version_data.peer_dependencies_meta.clone(),
+                    os: version_data.os.clone(),
+                    cpu: version_data.cpu.clone(),
+                    libc: version_data.libc.clone(),
+                });
+            }
+
+            queue = next;
+        }
+
+        let missing = missing_peers(&graph);
+        if missing.is_empty() {
+            break;
+        }
+        fetch_packuments(&fetch, missing.keys().cloned().collect(), &mut packuments, &mut uncached, &mut failed).await;
+
+        for (name, ranges) in missing {
+            let Some(resp) = packuments.get(&name) else { continue };
+            match peer_version(resp, &ranges) {
+                Some(version) => {
+                    println!("{}", format!("➕ Installing missing peer {}@{}", name, version).blue());
+                    queue.push(Request { edge: Edge::Peer, name, range: version, optional: false });
+                }
+                None => println!("{}", format!("⚠️ No version of {} matches the peer range {}",
+                                 name, ranges.join(" or ")).yellow()),
+            }
+        }
+
+        // Nothing left that can be installed
+        if queue.is_empty() {
+            break;
+        }
+    }
+
+    if !uncached.is_empty() {
+        anyhow::bail!("Offline mode is enabled and the cache has no metadata for: {}",
+                      uncached.into_iter().collect::<Vec<_>>().join(", "));
+    }
+
+    Ok(graph)
+}
+
+// Fetch the registry documents we don't have yet, concurrently
+async fn fetch_packuments<F, Fut>(
+    fetch: &F,
+    names: HashSet<String>,
+    packuments: &mut HashMap<String, registry::NpmResponse>,
+    uncached: &mut BTreeSet<String>,
+    failed: &mut HashMap<String, String>,
+) where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<registry::NpmResponse>>,
+{
+    let missing: Vec<String> = names.into_iter()
+        .filter(|name| !packuments.contains_key(name) && !uncached.contains(name) && !failed.contains_key(name))
+        .collect();
+
+    let fetched = stream::iter(missing)
+        .map(|name| {
+            let result = fetch(name.clone());
+            async move { (name, result.await) }
+        })
+        .buffer_unordered(crate::config::get().max_concurrent)
+        .collect::<Vec<_>>()
+        .await;
+
+    for (name, result) in fetched {
+        match result {
+            Ok(resp) => {
+                packuments.insert(name, resp);
+            }
+            Err(e) if e.is::<crate::cache::NotCached>() => {
+                uncached.insert(name);
+            }
+            Err(e) => {
+                failed.insert(name, e.to_string());
             }
         }
     }
-    
-    // Sort versions and get the latest matching one
-    if versions.is_empty() {
-        anyhow::bail!("No version found that matches requirement: {}", req);
+}
+
+// Required peers that no version of is anywhere in the graph, with every
+// range asked for
+pub fn missing_peers(graph: &ResolvedGraph) -> BTreeMap<String, Vec<String>> {
+    let present: HashSet<&str> = graph.packages.values().map(|node| node.name.as_str()).collect();
+    let mut missing: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for key in &graph.order {
+        let node = &graph.packages[key];
+        for (peer, range) in &node.peer_dependencies {
+            if node.is_optional_peer(peer) || present.contains(peer.as_str()) {
+                continue;
+            }
+            let ranges = missing.entry(peer.clone()).or_default();
+            if !ranges.contains(range) {
+                ranges.push(range.clone());
+            }
+        }
     }
-    
-    versions.sort();
-    Ok(versions.last().unwrap().to_string())
+    missing
+}
+
+// Highest version satisfying every range, or else the first one
+pub fn peer_version(resp: &registry::NpmResponse, ranges: &[String]) -> Option<String> {
+    let parsed: Vec<semver_range::Range> = ranges.iter()
+        .filter_map(|range| semver_range::Range::parse(range).ok())
+        .collect();
+    let first = parsed.first()?;
+
+    let candidates: Vec<&String> = resp.versions.keys()
+        .filter(|version| parsed.iter().all(|range| range.satisfies_str(version)))
+        .collect();
+
+    semver_range::max_satisfying(candidates, first)
+        .or_else(|| semver_range::max_satisfying(resp.versions.keys(), first))
+        .map(str::to_string)
 }
 
-pub async fn resolve_dependencies(deps: &HashMap<String, String>) -> Result<HashMap<String, String>> {
-    let mut resolved = HashMap::new();
-    
-    for (pkg, req) in deps {
-        let version = resolve_version(pkg, req).await?;
-        resolved.insert(pkg.clone(), version);
+// An installed package whose peer is missing or doesn't match the range
+#[derive(Debug)]
+pub struct PeerConflict {
+    pub peer: String,
+    // Installed version of the peer, if any
+    pub installed: Option<String>,
+    // ("name@version" of the dependent, range it wants)
+    pub wanted_by: Vec<(String, String)>,
+}
+
+// Check the peers of every placed package against the copy Node would load
+// from where that package is installed (install path -> node key). Conflicts
+// are grouped by peer and the version found.
+pub fn peer_conflicts(graph: &ResolvedGraph, placement: &HashMap<String, String>) -> Vec<PeerConflict> {
+    let mut conflicts: BTreeMap<(String, Option<String>), PeerConflict> = BTreeMap::new();
+    let mut paths: Vec<&String> = placement.keys().collect();
+    paths.sort();
+
+    for path in paths {
+        let key = &placement[path];
+        let Some(node) = graph.packages.get(key) else { continue };
+
+        for (peer, range) in &node.peer_dependencies {
+            let version = lockfile::resolve_path(path, peer, |p| placement.contains_key(p))
+                .and_then(|found| graph.packages.get(&placement[&found]))
+                .map(|provided| provided.version.clone());
+            let satisfied = match &version {
+                None => node.is_optional_peer(peer),
+                Some(version) => semver_range::Range::parse(range)
+                    .map(|r| r.satisfies_str(version))
+                    .unwrap_or(true),
+            };
+            if satisfied {
+                continue;
+            }
+
+            let wanted_by = &mut conflicts.entry((peer.clone(), version.clone()))
+                .or_insert_with(|| PeerConflict {
+                    peer: peer.clone(),
+                    installed: version,
+                    wanted_by: Vec::new(),
+                })
+                .wanted_by;
+            // The same package may be installed in several places
+            let wanted = (key.clone(), range.clone());
+            if !wanted_by.contains(&wanted) {
+                wanted_by.push(wanted);
+            }
+        }
     }
-    
-    Ok(resolved)
+
+    conflicts.into_values().collect()
 }
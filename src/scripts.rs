@@ -0,0 +1,357 @@
+use anyhow::Result;
+use colored::*;
+use serde_json::Value;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+// Running package.json scripts with the environment npm gives them, so
+// locally installed tools are on the PATH and scripts can read
+// `npm_package_*`, `npm_lifecycle_event` and `INIT_CWD`
+
+// The manifest of the package in `dir`, which only the project itself may
+// still call a.json
+fn manifest_path(dir: &Path) -> PathBuf {
+    dir.join(crate::manifest::manifest_file_in(dir))
+}
+
+// A package's package.json as plain JSON, for the npm_package_* variables
+pub fn read_package_json(project_dir: &Path) -> Result<Value> {
+    let path = manifest_path(project_dir);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Value::Object(Default::default())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// PATH for scripts in `dir`: the node_modules/.bin of `dir` and every
+// ancestor, nearest first, followed by the existing PATH
+pub fn bin_path(dir: &Path, path: Option<&OsString>) -> Result<OsString> {
+    let mut entries: Vec<PathBuf> = dir.ancestors().map(|d| d.join("node_modules").join(".bin")).collect();
+    if let Some(path) = path {
+        entries.extend(std::env::split_paths(path));
+    }
+    Ok(std::env::join_paths(entries)?)
+}
+
+// The manifest flattened into npm_package_* variables, e.g.
+// `npm_package_scripts_build` or `npm_package_dependencies_react`
+pub fn package_env(package_json: &Value) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+    flatten("npm_package", package_json, &mut env);
+    env
+}
+
+fn flatten(prefix: &str, value: &Value, env: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                // npm leaves out private fields and the readme
+                if key.starts_with('_') || (prefix == "npm_package" && key == "readme") {
+                    continue;
+                }
+                flatten(&format!("{}_{}", prefix, env_key(key)), value, env);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten(&format!("{}_{}", prefix, i), item, env);
+            }
+        }
+        Value::String(s) => env.push((prefix.to_string(), s.clone())),
+        Value::Bool(b) => env.push((prefix.to_string(), b.to_string())),
+        Value::Number(n) => env.push((prefix.to_string(), n.to_string())),
+        Value::Null => {}
+    }
+}
+
+// Characters that aren't valid in variable names become underscores
+fn env_key(key: &str) -> String {
+    key.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+// Everything a script run for `event` gets on top of the inherited
+// environment
+pub fn script_env(project_dir: &Path, package_json: &Value, event: &str, script: &str) -> Result<Vec<(String, OsString)>> {
+    let mut env: Vec<(String, OsString)> = package_env(package_json).into_iter()
+        .map(|(key, value)| (key, value.into()))
+        .collect();
+
+    let path = std::env::var_os("PATH");
+    env.push(("PATH".to_string(), bin_path(project_dir, path.as_ref())?));
+    env.push(("npm_lifecycle_event".to_string(), event.into()));
+    env.push(("npm_lifecycle_script".to_string(), script.into()));
+    env.push(("npm_package_json".to_string(), manifest_path(project_dir).into()));
+    env.push(("npm_config_user_agent".to_string(), format!("a/{}", env!("CARGO_PKG_VERSION")).into()));
+    if let Ok(exe) = std::env::current_exe() {
+        env.push(("npm_execpath".to_string(), exe.into()));
+    }
+
+    // Nested runs keep the directory the first command was started from
+    let init_cwd = match std::env::var_os("INIT_CWD") {
+        Some(dir) => dir,
+        None => std::env::current_dir()?.into(),
+    };
+    env.push(("INIT_CWD".to_string(), init_cwd));
+
+    Ok(env)
+}
+
+// What npm runs when a package is installed, in order
+pub const INSTALL_STAGES: [&str; 3] = ["preinstall", "install", "postinstall"];
+
+// The project's own lifecycle scripts, in npm's order: preinstall before its
+// dependencies are installed, the rest once they are
+pub const ROOT_STAGES_BEFORE_INSTALL: &[&str] = &["preinstall"];
+pub const ROOT_STAGES_AFTER_INSTALL: &[&str] = &["install", "postinstall", "prepublish", "prepare"];
+
+// Run the scripts the project in `project_dir` defines for these stages, the
+// same way `a run` does. `--ignore-scripts` skips them all.
+pub async fn run_root_scripts(project_dir: &Path, stages: &[&str], ignore_scripts: bool) -> Result<()> {
+    if ignore_scripts {
+        return Ok(());
+    }
+    let package_json = read_package_json(project_dir)?;
+    for stage in stages {
+        let script = package_json.get("scripts").and_then(|scripts| scripts.get(*stage)).and_then(Value::as_str);
+        if let Some(script) = script {
+            run_script(project_dir, &package_json, stage, script).await?;
+        }
+    }
+    Ok(())
+}
+
+// The install scripts of the package in `dir`. Like npm, a package with a
+// binding.gyp and no install script of its own is built with node-gyp.
+pub fn install_scripts(dir: &Path, package_json: &Value) -> Vec<(String, String)> {
+    let scripts = package_json.get("scripts");
+    let mut found: Vec<(String, String)> = INSTALL_STAGES.iter()
+        .filter_map(|stage| {
+            let script = scripts?.get(*stage)?.as_str()?;
+            Some((stage.to_string(), script.to_string()))
+        })
+        .collect();
+
+    let has_install = found.iter().any(|(stage, _)| stage == "install" || stage == "preinstall");
+    if !has_install && dir.join("binding.gyp").exists() {
+        let at = found.iter().position(|(stage, _)| stage == "postinstall").unwrap_or(found.len());
+        found.insert(at, ("install".to_string(), "node-gyp rebuild".to_string()));
+    }
+    found
+}
+
+// `script` followed by extra arguments, quoted so the shell passes each one
+// through unchanged
+pub fn with_args(script: &str, args: &[String]) -> String {
+    let mut command = script.to_string();
+    for arg in args {
+        command.push(' ');
+        command.push_str(&quote(arg));
+    }
+    command
+}
+
+// One shell word holding `arg` as-is
+#[cfg(not(windows))]
+pub fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(windows)]
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"&|<>^%".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("\"{}\"", arg.replace('"', "\"\""))
+    }
+}
+
+// A shell command running `script` in `project_dir` for `event`
+pub fn command(project_dir: &Path, package_json: &Value, event: &str, script: &str) -> Result<Command> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", script]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    };
+    command.current_dir(project_dir)
+        .envs(script_env(project_dir, package_json, event, script)?);
+    Ok(command)
+}
+
+// Run the script called `name` and fail with `ScriptFailed` if it does
+pub async fn run_script(project_dir: &Path, package_json: &Value, name: &str, script: &str) -> Result<()> {
+    println!("{}", format!("▶️ Running script: {}", name).blue());
+    let code = run(command(project_dir, package_json, name, script)?).await?;
+    if code != 0 {
+        return Err(ScriptFailed { name: name.to_string(), code }.into());
+    }
+    Ok(())
+}
+
+// A script exited unsuccessfully. `a` exits with the same code.
+#[derive(Debug)]
+pub struct ScriptFailed {
+    pub name: String,
+    pub code: i32,
+}
+
+impl fmt::Display for ScriptFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Script '{}' failed with exit code {}", self.name, self.code)
+    }
+}
+
+impl std::error::Error for ScriptFailed {}
+
+// Exit code of a finished script; killed by a signal counts as 128 + signal
+// like in a shell
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+// Process group of the script running right now, 0 when there is none
+#[cfg(unix)]
+static RUNNING: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+// Handle SIGINT, SIGTERM and SIGHUP for the whole run of `a`: while a script
+// runs they are passed on to its process group, otherwise `a` exits. Tokio
+// keeps its handlers once registered, so this is done once, at startup.
+#[cfg(unix)]
+pub fn handle_signals() -> Result<()> {
+    use std::sync::atomic::Ordering;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        loop {
+            let received = tokio::select! {
+                _ = interrupt.recv() => libc::SIGINT,
+                _ = terminate.recv() => libc::SIGTERM,
+                _ = hangup.recv() => libc::SIGHUP,
+            };
+            match RUNNING.load(Ordering::SeqCst) {
+                0 => std::process::exit(128 + received),
+                // SAFETY: kill has no memory safety requirements
+                pgid => unsafe {
+                    libc::kill(-pgid, received);
+                },
+            }
+        }
+    });
+    Ok(())
+}
+
+// Ctrl-C reaches scripts directly; `a` only has to stay alive until they exit
+#[cfg(not(unix))]
+pub fn handle_signals() -> Result<()> {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if RUNNING.load(std::sync::atomic::Ordering::SeqCst) == 0 {
+                std::process::exit(130);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+static RUNNING: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+// Run a script command with the terminal attached and return its exit
+// code. On Unix the script gets its own process group, which takes over
+// the terminal while it runs and receives the signals sent to `a` (see
+// `handle_signals`), so nothing is left running.
+#[cfg(unix)]
+pub async fn run(command: Command) -> Result<i32> {
+    use std::os::unix::process::CommandExt;
+    use std::sync::atomic::Ordering;
+
+    let mut command = command;
+    command.process_group(0);
+    let mut child = tokio::process::Command::from(command).spawn()?;
+    let pgid = child.id().map(|id| id as libc::pid_t);
+    let terminal = pgid.and_then(Terminal::hand_to);
+
+    RUNNING.store(pgid.unwrap_or(0), Ordering::SeqCst);
+    let status = child.wait().await;
+    RUNNING.store(0, Ordering::SeqCst);
+    drop(terminal);
+
+    Ok(exit_code(status?))
+}
+
+#[cfg(not(unix))]
+pub async fn run(command: Command) -> Result<i32> {
+    use std::sync::atomic::Ordering;
+
+    let mut child = tokio::process::Command::from(command).spawn()?;
+    RUNNING.fetch_add(1, Ordering::SeqCst);
+    let status = child.wait().await;
+    RUNNING.fetch_sub(1, Ordering::SeqCst);
+    Ok(exit_code(status?))
+}
+
+// The terminal in the foreground of a script's process group, handed back
+// to `a` when dropped
+#[cfg(unix)]
+struct Terminal {
+    pgrp: libc::pid_t,
+    // SIGTTOU disposition before it was ignored
+    sigttou: libc::sighandler_t,
+}
+
+#[cfg(unix)]
+impl Terminal {
+    fn hand_to(pgid: libc::pid_t) -> Option<Terminal> {
+        // SAFETY: plain system calls on the stdin descriptor; SIGTTOU is
+        // ignored so `a` may take the terminal back from the background
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) != 1 {
+                return None;
+            }
+            let pgrp = libc::getpgrp();
+            if libc::tcgetpgrp(libc::STDIN_FILENO) != pgrp {
+                // `a` itself runs in the background
+                return None;
+            }
+            let sigttou = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            if libc::tcsetpgrp(libc::STDIN_FILENO, pgid) != 0 {
+                libc::signal(libc::SIGTTOU, sigttou);
+                return None;
+            }
+            Some(Terminal { pgrp, sigttou })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // SAFETY: see `hand_to`
+        unsafe {
+            libc::tcsetpgrp(libc::STDIN_FILENO, self.pgrp);
+            libc::signal(libc::SIGTTOU, self.sigttou);
+        }
+    }
+}
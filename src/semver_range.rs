@@ -0,0 +1,428 @@
+use anyhow::Result;
+use semver::{BuildMetadata, Prerelease, Version};
+use std::cmp::Ordering;
+
+// npm (node-semver) compatible version ranges. The `semver` crate follows
+// Cargo's rules, which reject hyphen ranges and `||` unions and treat a bare
+// `1.2.3` as `^1.2.3`, so package.json ranges are matched with this instead.
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    // Accept sloppy versions like `v 1.2.3`, `01.2.3` and `1.2.3beta`
+    pub loose: bool,
+    // Let prerelease versions match any range they fall inside of
+    pub include_prerelease: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+#[derive(Debug, Clone)]
+enum Comparator {
+    Any,
+    Cmp(Op, Version),
+}
+
+#[derive(Debug, Clone)]
+pub struct Range {
+    // Comparator sets joined by `||`; a version must satisfy every comparator
+    // in at least one set
+    sets: Vec<Vec<Comparator>>,
+    options: Options,
+}
+
+// A possibly incomplete version such as `1`, `1.2.x` or `*`
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Prerelease,
+}
+
+impl Range {
+    // Ranges in manifests are parsed loosely, the same way npm does
+    pub fn parse(range: &str) -> Result<Range> {
+        Range::parse_with(range, Options { loose: true, include_prerelease: false })
+    }
+
+    pub fn parse_with(range: &str, options: Options) -> Result<Range> {
+        let mut sets = Vec::new();
+        for set in range.split("||") {
+            let comparators = parse_set(set.trim(), options)
+                .map_err(|e| anyhow::anyhow!("Invalid version range '{}': {}", range, e))?;
+            sets.push(comparators);
+        }
+
+        Ok(Range { sets, options })
+    }
+
+    pub fn satisfies(&self, version: &Version) -> bool {
+        self.sets.iter().any(|set| test_set(set, version, self.options))
+    }
+
+    // Like `satisfies`, for versions that still need parsing
+    pub fn satisfies_str(&self, version: &str) -> bool {
+        parse_version(version, self.options.loose)
+            .map(|v| self.satisfies(&v))
+            .unwrap_or(false)
+    }
+}
+
+// Return the highest version from the list that satisfies the range
+pub fn max_satisfying<'a, I>(versions: I, range: &Range) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    versions.into_iter()
+        .filter_map(|v| parse_version(v, true).map(|parsed| (v, parsed)))
+        .filter(|(_, parsed)| range.satisfies(parsed))
+        .max_by(|(_, a), (_, b)| compare(a, b))
+        .map(|(v, _)| v.as_str())
+}
+
+// Order two versions by precedence, ignoring build metadata like npm does
+pub fn compare(a: &Version, b: &Version) -> Ordering {
+    (a.major, a.minor, a.patch, &a.pre).cmp(&(b.major, b.minor, b.patch, &b.pre))
+}
+
+// Whether `a` is a newer version than `b`. A valid version is newer than an
+// invalid one, and two invalid ones are compared as text, so the answer never
+// depends on which of the two is seen first.
+pub fn is_newer(a: &str, b: &str) -> bool {
+    match (parse_version(a, true), parse_version(b, true)) {
+        (Some(a), Some(b)) => compare(&a, &b) == Ordering::Greater,
+        (Some(_), None) => true,
+        (None, Some(_)) => false,
+        (None, None) => a > b,
+    }
+}
+
+// Parse a single version, accepting a leading `v` or `=` like node-semver
+pub fn parse_version(input: &str, loose: bool) -> Option<Version> {
+    let trimmed = if loose {
+        input.trim().trim_start_matches(|c: char| c == 'v' || c == '=' || c.is_whitespace())
+    } else {
+        let t = input.trim();
+        t.strip_prefix('v').unwrap_or(t)
+    };
+
+    let partial = parse_partial(trimmed, loose).ok()?;
+    match partial {
+        Partial { major: Some(major), minor: Some(minor), patch: Some(patch), pre } => {
+            let mut version = Version::new(major, minor, patch);
+            version.pre = pre;
+            Some(version)
+        }
+        _ => None,
+    }
+}
+
+fn parse_set(set: &str, options: Options) -> Result<Vec<Comparator>> {
+    if set.is_empty() {
+        return Ok(vec![Comparator::Any]);
+    }
+
+    // Hyphen ranges: `1.2.3 - 2.3.4`
+    let words: Vec<&str> = set.split_whitespace().collect();
+    if words.len() == 3 && words[1] == "-" {
+        return hyphen_range(words[0], words[2], options);
+    }
+
+    // Glue operators to their versions so `>= 1.2.3` and `~ 1.0` parse
+    let mut tokens: Vec<String> = Vec::new();
+    let mut pending = String::new();
+    for word in words {
+        if word.chars().all(|c| matches!(c, '<' | '>' | '=' | '~' | '^')) {
+            pending.push_str(word);
+            continue;
+        }
+        tokens.push(format!("{}{}", pending, word));
+        pending.clear();
+    }
+    if !pending.is_empty() {
+        anyhow::bail!("dangling operator '{}'", pending);
+    }
+
+    let mut comparators = Vec::new();
+    for token in tokens {
+        comparators.extend(parse_comparator(&token, options)?);
+    }
+    Ok(comparators)
+}
+
+fn parse_comparator(token: &str, options: Options) -> Result<Vec<Comparator>> {
+    if let Some(rest) = token.strip_prefix("~>").or_else(|| token.strip_prefix('~')) {
+        return tilde(&parse_partial(strip_v(rest), options.loose)?, options);
+    }
+    if let Some(rest) = token.strip_prefix('^') {
+        return caret(&parse_partial(strip_v(rest), options.loose)?, options);
+    }
+
+    let (op, rest) = if let Some(rest) = token.strip_prefix(">=") {
+        (Some(Op::Ge), rest)
+    } else if let Some(rest) = token.strip_prefix("<=") {
+        (Some(Op::Le), rest)
+    } else if let Some(rest) = token.strip_prefix('>') {
+        (Some(Op::Gt), rest)
+    } else if let Some(rest) = token.strip_prefix('<') {
+        (Some(Op::Lt), rest)
+    } else if let Some(rest) = token.strip_prefix('=') {
+        (Some(Op::Eq), rest.trim_start_matches('='))
+    } else {
+        (None, token)
+    };
+
+    let partial = parse_partial(strip_v(rest), options.loose)?;
+    x_range(op, &partial, options)
+}
+
+fn strip_v(s: &str) -> &str {
+    s.trim_start_matches(['v', '='])
+}
+
+fn parse_partial(input: &str, loose: bool) -> Result<Partial> {
+    // Build metadata never affects matching
+    let input = input.split('+').next().unwrap_or("");
+
+    let main_len = input
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | 'x' | 'X' | '*')))
+        .unwrap_or(input.len());
+    let (main, rest) = input.split_at(main_len);
+
+    let pre = if rest.is_empty() {
+        Prerelease::EMPTY
+    } else if let Some(pre) = rest.strip_prefix('-') {
+        Prerelease::new(pre).map_err(|e| anyhow::anyhow!("invalid prerelease '{}': {}", pre, e))?
+    } else if loose {
+        Prerelease::new(rest).map_err(|e| anyhow::anyhow!("invalid prerelease '{}': {}", rest, e))?
+    } else {
+        anyhow::bail!("invalid version '{}'", input);
+    };
+
+    if main.is_empty() {
+        anyhow::bail!("invalid version '{}'", input);
+    }
+
+    let mut parts = [None, None, None];
+    let mut wildcard = false;
+    let pieces: Vec<&str> = main.split('.').collect();
+    if pieces.len() > 3 {
+        anyhow::bail!("invalid version '{}'", input);
+    }
+
+    for (i, piece) in pieces.iter().enumerate() {
+        if piece.is_empty() {
+            anyhow::bail!("invalid version '{}'", input);
+        }
+        if wildcard || matches!(*piece, "x" | "X" | "*") {
+            // Everything after a wildcard is a wildcard too: `1.x.3` == `1.x`
+            wildcard = true;
+            continue;
+        }
+        if !piece.chars().all(|c| c.is_ascii_digit()) {
+            anyhow::bail!("invalid version '{}'", input);
+        }
+        if !loose && piece.len() > 1 && piece.starts_with('0') {
+            anyhow::bail!("leading zero in '{}'", input);
+        }
+        parts[i] = Some(piece.parse::<u64>()?);
+    }
+
+    if !pre.is_empty() && parts.iter().any(Option::is_none) {
+        anyhow::bail!("prerelease on a partial version '{}'", input);
+    }
+
+    Ok(Partial { major: parts[0], minor: parts[1], patch: parts[2], pre })
+}
+
+fn version(major: u64, minor: u64, patch: u64, pre: &str) -> Version {
+    let mut v = Version::new(major, minor, patch);
+    if !pre.is_empty() {
+        v.pre = Prerelease::new(pre).expect("static prerelease identifier");
+    }
+    v.build = BuildMetadata::EMPTY;
+    v
+}
+
+// Lower bounds of desugared ranges start at `-0` when prereleases are included
+fn lower_pre(options: Options) -> &'static str {
+    if options.include_prerelease { "0" } else { "" }
+}
+
+fn full(p: &Partial) -> Option<Version> {
+    match (p.major, p.minor, p.patch) {
+        (Some(major), Some(minor), Some(patch)) => {
+            let mut v = Version::new(major, minor, patch);
+            v.pre = p.pre.clone();
+            Some(v)
+        }
+        _ => None,
+    }
+}
+
+// `~1.2.3` := `>=1.2.3 <1.3.0-0`, `~1.2` := `>=1.2.0 <1.3.0-0`, `~1` := `>=1.0.0 <2.0.0-0`
+fn tilde(p: &Partial, options: Options) -> Result<Vec<Comparator>> {
+    let pre = lower_pre(options);
+    Ok(match (p.major, p.minor, p.patch) {
+        (None, _, _) => vec![Comparator::Any],
+        (Some(major), None, _) => vec![
+            Comparator::Cmp(Op::Ge, version(major, 0, 0, pre)),
+            Comparator::Cmp(Op::Lt, version(major + 1, 0, 0, "0")),
+        ],
+        (Some(major), Some(minor), None) => vec![
+            Comparator::Cmp(Op::Ge, version(major, minor, 0, pre)),
+            Comparator::Cmp(Op::Lt, version(major, minor + 1, 0, "0")),
+        ],
+        (Some(major), Some(minor), Some(_)) => vec![
+            Comparator::Cmp(Op::Ge, full(p).expect("complete version")),
+            Comparator::Cmp(Op::Lt, version(major, minor + 1, 0, "0")),
+        ],
+    })
+}
+
+// `^` allows changes that do not modify the left-most non-zero component
+fn caret(p: &Partial, options: Options) -> Result<Vec<Comparator>> {
+    let pre = lower_pre(options);
+    Ok(match (p.major, p.minor, p.patch) {
+        (None, _, _) => vec![Comparator::Any],
+        (Some(major), None, _) => vec![
+            Comparator::Cmp(Op::Ge, version(major, 0, 0, pre)),
+            Comparator::Cmp(Op::Lt, version(major + 1, 0, 0, "0")),
+        ],
+        (Some(major), Some(minor), None) => {
+            let upper = if major == 0 {
+                version(0, minor + 1, 0, "0")
+            } else {
+                version(major + 1, 0, 0, "0")
+            };
+            vec![
+                Comparator::Cmp(Op::Ge, version(major, minor, 0, pre)),
+                Comparator::Cmp(Op::Lt, upper),
+            ]
+        }
+        (Some(major), Some(minor), Some(patch)) => {
+            let upper = if major > 0 {
+                version(major + 1, 0, 0, "0")
+            } else if minor > 0 {
+                version(0, minor + 1, 0, "0")
+            } else {
+                version(0, 0, patch + 1, "0")
+            };
+            vec![
+                Comparator::Cmp(Op::Ge, full(p).expect("complete version")),
+                Comparator::Cmp(Op::Lt, upper),
+            ]
+        }
+    })
+}
+
+// Plain versions, x-ranges and primitive operators with partial versions
+fn x_range(op: Option<Op>, p: &Partial, options: Options) -> Result<Vec<Comparator>> {
+    let pre = lower_pre(options);
+
+    if let Some(v) = full(p) {
+        return Ok(vec![Comparator::Cmp(op.unwrap_or(Op::Eq), v)]);
+    }
+
+    let Some(major) = p.major else {
+        // `*`, `x` and friends: only `<*` and `>*` match nothing
+        return Ok(match op {
+            Some(Op::Lt) | Some(Op::Gt) => vec![Comparator::Cmp(Op::Lt, version(0, 0, 0, "0"))],
+            _ => vec![Comparator::Any],
+        });
+    };
+
+    Ok(match (op, p.minor) {
+        // `>1` := `>=2.0.0`, `>1.2` := `>=1.3.0`
+        (Some(Op::Gt), None) => vec![Comparator::Cmp(Op::Ge, version(major + 1, 0, 0, pre))],
+        (Some(Op::Gt), Some(minor)) => vec![Comparator::Cmp(Op::Ge, version(major, minor + 1, 0, pre))],
+        (Some(Op::Ge), minor) => vec![Comparator::Cmp(Op::Ge, version(major, minor.unwrap_or(0), 0, pre))],
+        // `<1.2` := `<1.2.0-0`
+        (Some(Op::Lt), minor) => vec![Comparator::Cmp(Op::Lt, version(major, minor.unwrap_or(0), 0, "0"))],
+        // `<=1` := `<2.0.0-0`, `<=1.2` := `<1.3.0-0`
+        (Some(Op::Le), None) => vec![Comparator::Cmp(Op::Lt, version(major + 1, 0, 0, "0"))],
+        (Some(Op::Le), Some(minor)) => vec![Comparator::Cmp(Op::Lt, version(major, minor + 1, 0, "0"))],
+        // `1` := `>=1.0.0 <2.0.0-0`, `1.2` := `>=1.2.0 <1.3.0-0`
+        (Some(Op::Eq) | None, None) => vec![
+            Comparator::Cmp(Op::Ge, version(major, 0, 0, pre)),
+            Comparator::Cmp(Op::Lt, version(major + 1, 0, 0, "0")),
+        ],
+        (Some(Op::Eq) | None, Some(minor)) => vec![
+            Comparator::Cmp(Op::Ge, version(major, minor, 0, pre)),
+            Comparator::Cmp(Op::Lt, version(major, minor + 1, 0, "0")),
+        ],
+    })
+}
+
+// `1.2 - 2.3.4` := `>=1.2.0 <=2.3.4`, `1.2.3 - 2.3` := `>=1.2.3 <2.4.0-0`
+fn hyphen_range(from: &str, to: &str, options: Options) -> Result<Vec<Comparator>> {
+    let from = parse_partial(strip_v(from), options.loose)?;
+    let to = parse_partial(strip_v(to), options.loose)?;
+    let pre = lower_pre(options);
+
+    let mut comparators = Vec::new();
+    match (from.major, from.minor) {
+        (None, _) => {}
+        (Some(major), None) => comparators.push(Comparator::Cmp(Op::Ge, version(major, 0, 0, pre))),
+        (Some(major), Some(minor)) => match full(&from) {
+            Some(v) => comparators.push(Comparator::Cmp(Op::Ge, v)),
+            None => comparators.push(Comparator::Cmp(Op::Ge, version(major, minor, 0, pre))),
+        },
+    }
+
+    match (to.major, to.minor) {
+        (None, _) => {}
+        (Some(major), None) => comparators.push(Comparator::Cmp(Op::Lt, version(major + 1, 0, 0, "0"))),
+        (Some(major), Some(minor)) => match full(&to) {
+            Some(v) => comparators.push(Comparator::Cmp(Op::Le, v)),
+            None => comparators.push(Comparator::Cmp(Op::Lt, version(major, minor + 1, 0, "0"))),
+        },
+    }
+
+    if comparators.is_empty() {
+        comparators.push(Comparator::Any);
+    }
+    Ok(comparators)
+}
+
+fn test_comparator(comparator: &Comparator, v: &Version) -> bool {
+    match comparator {
+        Comparator::Any => true,
+        Comparator::Cmp(op, bound) => {
+            let ord = compare(v, bound);
+            match op {
+                Op::Lt => ord == Ordering::Less,
+                Op::Le => ord != Ordering::Greater,
+                Op::Gt => ord == Ordering::Greater,
+                Op::Ge => ord != Ordering::Less,
+                Op::Eq => ord == Ordering::Equal,
+            }
+        }
+    }
+}
+
+fn test_set(set: &[Comparator], v: &Version, options: Options) -> bool {
+    if !set.iter().all(|c| test_comparator(c, v)) {
+        return false;
+    }
+
+    // A prerelease only matches when a comparator in the same set opts in to
+    // prereleases of that exact major.minor.patch tuple
+    if !v.pre.is_empty() && !options.include_prerelease {
+        return set.iter().any(|c| match c {
+            Comparator::Any => false,
+            Comparator::Cmp(_, bound) => {
+                !bound.pre.is_empty()
+                    && (bound.major, bound.minor, bound.patch) == (v.major, v.minor, v.patch)
+            }
+        });
+    }
+
+    true
+}
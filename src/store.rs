@@ -0,0 +1,281 @@
+use anyhow::Result;
+use colored::*;
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::integrity::Integrity;
+use crate::lockfile::{self, Lockfile, LockedPackage};
+
+// The isolated linker. Every package version is extracted once into a global
+// store under the cache directory, keyed by its integrity hash, and
+// hardlinked from there into `node_modules/.a/<pkg>@<ver>/node_modules/<pkg>`.
+// Next to it, symlinks to its dependencies are all a package can see;
+// `node_modules/<pkg>` only links the project's own dependencies.
+
+const VIRTUAL_STORE: &str = ".a";
+
+pub fn store_dir() -> PathBuf {
+    crate::config::get().cache_dir.join("store").join("v1")
+}
+
+// A package ready to be linked: its files in the store and the exact
+// versions of the packages it may require
+#[derive(Debug, Clone)]
+pub struct StoredPackage {
+    pub name: String,
+    pub version: String,
+    pub files: PathBuf,
+    pub dependencies: BTreeMap<String, String>,
+}
+
+// Whether a locked package can be linked without downloading anything
+pub fn is_stored(locked: &LockedPackage) -> bool {
+    locked.dist()
+        .and_then(|dist| Integrity::for_dist(&dist).ok().flatten())
+        .map(|integrity| store_dir().join(integrity.content_key()).exists())
+        .unwrap_or(false)
+}
+
+// Install the packages of a lockfile (already filtered for this platform)
+// through the store. `roots` are the project's own dependencies.
+pub async fn install_isolated(lock: &Lockfile, roots: &[String], max_concurrent: usize) -> Result<()> {
+    // One entry per package version, wherever the hoisted plan put it
+    let mut unique: BTreeMap<String, (String, &LockedPackage, BTreeMap<String, String>)> = BTreeMap::new();
+    for (path, locked) in &lock.packages {
+        let name = lockfile::package_name(path).to_string();
+        let key = crate::resolver::node_key(&name, &locked.version);
+        if unique.contains_key(&key) {
+            continue;
+        }
+        let mut dependencies = locked.dependencies.clone();
+        dependencies.extend(locked.optional_dependencies.clone());
+        for peer in locked.peer_dependencies.keys() {
+            if let Some((_, provided)) = lock.find_dependency(path, peer) {
+                dependencies.insert(peer.clone(), provided.version.clone());
+            }
+        }
+        unique.insert(key, (name, locked, dependencies));
+    }
+
+    println!("{}", format!("🗄️ Adding {} packages to the store...", unique.len()).blue());
+    let results = stream::iter(unique.into_values())
+        .map(|(name, locked, dependencies)| async move {
+            let result = ensure_stored(&name, locked).await;
+            (name, locked, dependencies, result)
+        })
+        .buffer_unordered(max_concurrent)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut stored = Vec::new();
+    let mut failures = 0;
+    for (name, locked, dependencies, result) in results {
+        match result {
+            Ok(files) => stored.push(StoredPackage { name, version: locked.version.clone(), files, dependencies }),
+            Err(e) if locked.optional => {
+                println!("{}", format!("⚠️ Skipped optional {}@{}: {}", name, locked.version, e).yellow());
+            }
+            Err(e) => {
+                failures += 1;
+                println!("{}", format!("❌ Failed to install {}@{}: {}", name, locked.version, e).red());
+            }
+        }
+    }
+    if failures > 0 {
+        anyhow::bail!("{} packages failed to install", failures);
+    }
+
+    let top_level: BTreeMap<String, String> = roots.iter()
+        .filter_map(|name| lock.packages.get(&lockfile::install_path(name)).map(|p| (name.clone(), p.version.clone())))
+        .collect();
+
+    println!("{}", format!("🔗 Linking {} packages from the store", stored.len()).blue());
+    link(Path::new("."), &stored, &top_level)
+}
+
+// Files of a package in the store, extracting its tarball the first time
+async fn ensure_stored(pkg: &str, locked: &LockedPackage) -> Result<PathBuf> {
+    let dist = locked.dist();
+    let expected = match &dist {
+        Some(dist) => Integrity::for_dist(dist)?,
+        None => None,
+    };
+    if let Some(expected) = &expected {
+        let path = store_dir().join(expected.content_key());
+        if path.exists() {
+            return Ok(path);
+        }
+    }
+
+    let bytes = crate::registry::fetch_tarball(pkg, &locked.version, dist.as_ref()).await?;
+    let key = expected.unwrap_or_else(|| Integrity::of(&bytes)).content_key();
+    let path = store_dir().join(&key);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    // Extract next to the final location and move it into place, so a store
+    // entry is either complete or absent
+    let tmp = temp_path(&store_dir(), &key);
+    fs::create_dir_all(&tmp)?;
+    let report = crate::extract::extract_package(&bytes, &tmp)?;
+    for (entry, reason) in &report.rejected {
+        eprintln!("{}", format!("⚠️ Skipped unsafe entry '{}' in {}@{}: {}",
+                  entry, pkg, locked.version, reason).yellow());
+    }
+    if let Err(e) = fs::rename(&tmp, &path) {
+        let _ = fs::remove_dir_all(&tmp);
+        // Another install stored the same package first
+        if !path.exists() {
+            return Err(e.into());
+        }
+    }
+    Ok(path)
+}
+
+// Where a package version lives inside `node_modules/.a`
+fn virtual_dir(project: &Path, name: &str, version: &str) -> PathBuf {
+    project.join("node_modules").join(VIRTUAL_STORE).join(virtual_dir_name(name, version))
+}
+
+// The directory a package is linked to, where its install scripts run
+pub fn package_dir(project: &Path, name: &str, version: &str) -> PathBuf {
+    virtual_dir(project, name, version).join("node_modules").join(name)
+}
+
+// Lay out node_modules for stored packages under `project`. `top_level` maps
+// the project's dependencies to their versions.
+pub fn link(project: &Path, packages: &[StoredPackage], top_level: &BTreeMap<String, String>) -> Result<()> {
+    let available: HashMap<(&str, &str), &StoredPackage> = packages.iter()
+        .map(|p| ((p.name.as_str(), p.version.as_str()), p))
+        .collect();
+    let virtual_store = project.join("node_modules").join(VIRTUAL_STORE);
+
+    for package in packages {
+        let dir = virtual_dir(project, &package.name, &package.version);
+
+        // Existing entries are complete: they are moved into place when done
+        if !dir.exists() {
+            let tmp = temp_path(&virtual_store, &format!("{}@{}", package.name.replace('/', "+"), package.version));
+            hardlink_tree(&package.files, &tmp.join("node_modules").join(&package.name))?;
+            fs::rename(&tmp, &dir)?;
+        }
+
+        for (dep, version) in &package.dependencies {
+            if dep == &package.name || !available.contains_key(&(dep.as_str(), version.as_str())) {
+                continue;
+            }
+            let link_path = dir.join("node_modules").join(dep);
+            let target = format!("{}{}/node_modules/{}",
+                                 "../".repeat(2 + scope_depth(dep)), virtual_dir_name(dep, version), dep);
+            replace_with_symlink(&link_path, Path::new(&target))?;
+            crate::bin::link_bins(&dir.join("node_modules"), dep)?;
+        }
+    }
+
+    // The project only sees what it depends on directly
+    let node_modules = project.join("node_modules");
+    remove_undeclared(&node_modules, top_level)?;
+    for (name, version) in top_level {
+        if !available.contains_key(&(name.as_str(), version.as_str())) {
+            continue;
+        }
+        let target = format!("{}{}/{}/node_modules/{}",
+                             "../".repeat(scope_depth(name)), VIRTUAL_STORE, virtual_dir_name(name, version), name);
+        replace_with_symlink(&node_modules.join(name), Path::new(&target))?;
+        crate::bin::link_bins(&node_modules, name)?;
+    }
+    crate::bin::prune_bins(&node_modules)?;
+
+    Ok(())
+}
+
+fn virtual_dir_name(name: &str, version: &str) -> String {
+    format!("{}@{}", name.replace('/', "+"), version)
+}
+
+// "@scope/name" is one folder deeper than "name"
+fn scope_depth(name: &str) -> usize {
+    name.matches('/').count()
+}
+
+// Remove top-level entries left over from a hoisted install, so packages
+// that aren't dependencies of the project can't be required by accident
+fn remove_undeclared(node_modules: &Path, top_level: &BTreeMap<String, String>) -> Result<()> {
+    let Ok(entries) = fs::read_dir(node_modules) else { return Ok(()) };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        if name.starts_with('@') && entry.file_type()?.is_dir() {
+            for scoped in fs::read_dir(entry.path())?.flatten() {
+                let full = format!("{}/{}", name, scoped.file_name().to_string_lossy());
+                if !top_level.contains_key(&full) {
+                    remove_path(&scoped.path())?;
+                }
+            }
+        } else if !top_level.contains_key(&name) {
+            remove_path(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+// Recreate `src` under `dst` with hardlinks, copying where the store and the
+// project are on different file systems
+fn hardlink_tree(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            hardlink_tree(&entry.path(), &target)?;
+        } else if fs::hard_link(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn replace_with_symlink(link: &Path, target: &Path) -> io::Result<()> {
+    if fs::read_link(link).map(|current| current == target).unwrap_or(false) {
+        return Ok(());
+    }
+    if fs::symlink_metadata(link).is_ok() {
+        remove_path(link)?;
+    }
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    symlink_dir(target, link)
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+// A sibling of `dir/name` no other install uses at the same time
+fn temp_path(dir: &Path, name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    dir.join(format!(".tmp-{}-{}-{}", std::process::id(), n, name.replace('/', "+")))
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod tests {
+    use crate::bin;
+    use serde_json::json;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn install(node_modules: &Path, name: &str, package_json: serde_json::Value, files: &[&str]) {
+        let dir = node_modules.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("package.json"), package_json.to_string()).unwrap();
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "#!/usr/bin/env node\n").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bin_entries() {
+        let single = bin::bin_entries(&json!({ "name": "@vitejs/create-vite", "bin": "./index.js" }));
+        assert_eq!(single.into_iter().collect::<Vec<_>>(), vec![("create-vite".to_string(), PathBuf::from("index.js"))]);
+
+        let map = bin::bin_entries(&json!({ "name": "typescript", "bin": { "tsc": "bin/tsc", "tsserver": "./bin/tsserver" } }));
+        assert_eq!(map["tsc"], PathBuf::from("bin/tsc"));
+        assert_eq!(map["tsserver"], PathBuf::from("bin/tsserver"));
+
+        // Nothing may point outside the package or .bin
+        let unsafe_entries = bin::bin_entries(&json!({ "bin": { "a": "../../evil.js", "b": "/etc/passwd", "..": "x.js" } }));
+        assert!(unsafe_entries.is_empty());
+
+        assert!(bin::bin_entries(&json!({ "name": "lodash" })).is_empty());
+    }
+
+    #[test]
+    fn test_directories_bin() {
+        let node_modules = tempfile::tempdir().unwrap();
+        install(node_modules.path(), "tools", json!({ "name": "tools", "directories": { "bin": "./scripts" } }),
+                &["scripts/one", "scripts/nested/two", "lib/index.js"]);
+
+        let bins = bin::read_bins(&node_modules.path().join("tools")).unwrap();
+        assert_eq!(bins.keys().collect::<Vec<_>>(), vec!["one", "two"]);
+        assert_eq!(bins["two"], PathBuf::from("scripts/nested/two"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_and_unlink_bins() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let node_modules = tempfile::tempdir().unwrap();
+        let node_modules = node_modules.path();
+        install(node_modules, "vite", json!({ "name": "vite", "bin": { "vite": "bin/vite.js" } }), &["bin/vite.js"]);
+        install(node_modules, "@scope/cli", json!({ "name": "@scope/cli", "bin": "cli.js" }), &["cli.js"]);
+
+        assert_eq!(bin::link_bins(node_modules, "vite").unwrap(), vec!["vite"]);
+        assert_eq!(bin::link_bins(node_modules, "@scope/cli").unwrap(), vec!["cli"]);
+
+        let vite = node_modules.join(".bin/vite");
+        assert_eq!(fs::read_link(&vite).unwrap(), PathBuf::from("../vite/bin/vite.js"));
+        assert_eq!(fs::metadata(&vite).unwrap().permissions().mode() & 0o111, 0o111);
+        assert!(node_modules.join(".bin/cli").exists());
+
+        bin::unlink_bins(node_modules, "@scope/cli").unwrap();
+        assert!(fs::symlink_metadata(node_modules.join(".bin/cli")).is_err());
+        assert!(vite.exists());
+
+        fs::remove_dir_all(node_modules.join("vite")).unwrap();
+        bin::prune_bins(node_modules).unwrap();
+        assert!(fs::symlink_metadata(&vite).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, Linker, DEFAULT_REGISTRY};
+    use crate::npmrc::Npmrc;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn config_from(vars: &[(&str, &str)]) -> anyhow::Result<Config> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::from_sources(|key| vars.get(key).cloned(), Npmrc::default())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = config_from(&[]).unwrap();
+        assert_eq!(config.registry, DEFAULT_REGISTRY);
+        assert!(config.cache_dir.ends_with(".a-pm-cache"));
+        assert!(config.max_concurrent >= 1);
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let config = config_from(&[
+            ("A_REGISTRY", "https://npm.internal.example.com/"),
+            ("A_CACHE_DIR", "/mnt/ci-cache/a"),
+            ("A_MAX_CONCURRENT", "4"),
+        ]).unwrap();
+
+        assert_eq!(config.registry, "https://npm.internal.example.com");
+        assert_eq!(config.cache_dir, PathBuf::from("/mnt/ci-cache/a"));
+        assert_eq!(config.max_concurrent, 4);
+    }
+
+    #[test]
+    fn test_invalid_max_concurrent() {
+        assert!(config_from(&[("A_MAX_CONCURRENT", "0")]).is_err());
+        assert!(config_from(&[("A_MAX_CONCURRENT", "lots")]).is_err());
+        // Empty values fall back to the default
+        assert!(config_from(&[("A_MAX_CONCURRENT", "")]).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_settings() {
+        let mut npmrc = Npmrc::default();
+        npmrc.merge("fetch-timeout=5000\nfetch-retries=1\n", |_| None).unwrap();
+        let config = Config::from_sources(|_| None, npmrc.clone()).unwrap();
+        assert_eq!(config.fetch_timeout, Some(std::time::Duration::from_secs(5)));
+        assert_eq!(config.fetch_retries, 1);
+
+        // The environment wins over .npmrc
+        let config = Config::from_sources(|key| match key {
+            "A_FETCH_TIMEOUT" => Some("60".to_string()),
+            "A_FETCH_RETRIES" => Some("0".to_string()),
+            _ => None,
+        }, npmrc).unwrap();
+        assert_eq!(config.fetch_timeout, Some(std::time::Duration::from_secs(60)));
+        assert_eq!(config.fetch_retries, 0);
+
+        assert!(config_from(&[("A_FETCH_TIMEOUT", "soon")]).is_err());
+
+        // Large tarballs get five minutes by default; 0 means no timeout
+        assert_eq!(config_from(&[]).unwrap().fetch_timeout, Some(std::time::Duration::from_secs(300)));
+        let mut npmrc = Npmrc::default();
+        npmrc.merge("fetch-timeout=0\n", |_| None).unwrap();
+        assert_eq!(Config::from_sources(|_| None, npmrc).unwrap().fetch_timeout, None);
+        assert_eq!(config_from(&[("A_FETCH_TIMEOUT", "0")]).unwrap().fetch_timeout, None);
+        assert!(config_from(&[("A_FETCH_RETRIES", "-1")]).is_err());
+    }
+
+    #[test]
+    fn test_node_linker() {
+        assert_eq!(config_from(&[]).unwrap().linker, Linker::Hoisted);
+        assert_eq!(config_from(&[("A_NODE_LINKER", "isolated")]).unwrap().linker, Linker::Isolated);
+        assert!(config_from(&[("A_NODE_LINKER", "pnp")]).is_err());
+
+        let mut npmrc = Npmrc::default();
+        npmrc.merge("node-linker=isolated\n", |_| None).unwrap();
+        let config = Config::from_sources(|_| None, npmrc.clone()).unwrap();
+        assert_eq!(config.linker, Linker::Isolated);
+
+        let config = Config::from_sources(|key| (key == "A_NODE_LINKER").then(|| "hoisted".to_string()), npmrc).unwrap();
+        assert_eq!(config.linker, Linker::Hoisted);
+    }
+}
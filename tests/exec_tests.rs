@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod tests {
+    use crate::exec;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn bins(names: &[&str]) -> BTreeMap<String, PathBuf> {
+        names.iter().map(|n| (n.to_string(), PathBuf::from(format!("bin/{}.js", n)))).collect()
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(exec::parse_spec("prettier"), ("prettier".to_string(), "latest".to_string()));
+        assert_eq!(exec::parse_spec("create-vite@5"), ("create-vite".to_string(), "5".to_string()));
+        assert_eq!(exec::parse_spec("@vue/cli"), ("@vue/cli".to_string(), "latest".to_string()));
+        assert_eq!(exec::parse_spec("@vue/cli@^5.0.0"), ("@vue/cli".to_string(), "^5.0.0".to_string()));
+        assert_eq!(exec::parse_spec("cowsay@"), ("cowsay".to_string(), "latest".to_string()));
+        assert_eq!(exec::parse_spec("é@1"), ("é".to_string(), "1".to_string()));
+        assert_eq!(exec::parse_spec("é"), ("é".to_string(), "latest".to_string()));
+        assert_eq!(exec::parse_spec(""), ("".to_string(), "latest".to_string()));
+    }
+
+    #[test]
+    fn test_pick_bin() {
+        assert_eq!(exec::pick_bin("create-vite", &bins(&["cva"])).unwrap(), "cva");
+        assert_eq!(exec::pick_bin("typescript", &bins(&["tsc", "tsserver"])).unwrap_err().to_string(),
+                   "typescript has several commands and none is named after it: tsc, tsserver");
+        assert_eq!(exec::pick_bin("@scope/tool", &bins(&["tool", "tool-helper"])).unwrap(), "tool");
+        assert!(exec::pick_bin("lodash", &bins(&[])).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_bin_walks_up() {
+        let root = tempfile::tempdir().unwrap();
+        let bin_dir = root.path().join("node_modules/.bin");
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        std::fs::write(bin_dir.join("vite"), "").unwrap();
+        let nested = root.path().join("packages/app");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(exec::find_bin(&nested, "vite"), Some(bin_dir.join("vite")));
+        assert_eq!(exec::find_bin(&nested, "tsc"), None);
+    }
+}
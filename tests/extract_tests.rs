@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use crate::extract;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::path::PathBuf;
+
+    enum Entry<'a> {
+        File(&'a str, &'a str, u32),
+        Dir(&'a str),
+        Symlink(&'a str, &'a str),
+        Hardlink(&'a str, &'a str),
+    }
+
+    // Write entry names straight into the header so the tarball can contain
+    // paths the tar crate itself would refuse to create
+    fn set_raw_name(header: &mut tar::Header, name: &str) {
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.name = [0; 100];
+        gnu.name[..name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    fn craft_tarball(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            let (name, data): (&str, &[u8]) = match entry {
+                Entry::File(name, content, mode) => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_mode(*mode);
+                    (name, content.as_bytes())
+                }
+                Entry::Dir(name) => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(0o700);
+                    (name, &[])
+                }
+                Entry::Symlink(name, target) | Entry::Hardlink(name, target) => {
+                    let kind = if matches!(entry, Entry::Symlink(..)) {
+                        tar::EntryType::Symlink
+                    } else {
+                        tar::EntryType::Link
+                    };
+                    header.set_entry_type(kind);
+                    header.set_link_name(target).unwrap();
+                    (name, &[])
+                }
+            };
+            set_raw_name(&mut header, name);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_strips_first_component_whatever_its_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("pkg");
+        let tarball = craft_tarball(&[
+            Entry::Dir("node/"),
+            Entry::File("node/package.json", "{}", 0o644),
+            Entry::File("node/lib/index.js", "module.exports = 1", 0o644),
+        ]);
+
+        let report = extract::extract_package(&tarball, &dest).unwrap();
+        assert_eq!(report.files, 2);
+        assert!(report.rejected.is_empty());
+        assert!(dest.join("package.json").exists());
+        assert!(dest.join("lib/index.js").exists());
+    }
+
+    #[test]
+    fn test_rejects_escaping_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("pkg");
+        let tarball = craft_tarball(&[
+            Entry::File("package/index.js", "ok", 0o644),
+            Entry::File("package/../../escaped.txt", "pwned", 0o644),
+            Entry::File("../escaped-too.txt", "pwned", 0o644),
+            Entry::File("/tmp/absolute.txt", "pwned", 0o644),
+            Entry::File("package\\..\\..\\backslash.txt", "pwned", 0o644),
+            Entry::Symlink("package/link", "/etc/passwd"),
+            Entry::Hardlink("package/hard", "../../outside"),
+        ]);
+
+        let report = extract::extract_package(&tarball, &dest).unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(report.rejected.len(), 6);
+        assert!(dest.join("index.js").exists());
+        assert!(!dest.join("link").exists());
+        assert!(!dir.path().join("escaped.txt").exists());
+        assert!(!dir.path().join("escaped-too.txt").exists());
+    }
+
+    #[test]
+    fn test_sanitize_path() {
+        assert_eq!(extract::sanitize_path("package/a/b.js").unwrap(), Some(PathBuf::from("a/b.js")));
+        assert_eq!(extract::sanitize_path("package/./a.js").unwrap(), Some(PathBuf::from("a.js")));
+        assert_eq!(extract::sanitize_path("package/").unwrap(), None);
+        assert!(extract::sanitize_path("package/a/../../b").is_err());
+        assert!(extract::sanitize_path("/etc/passwd").is_err());
+        assert!(extract::sanitize_path("C:/Windows/evil.dll").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_normalizes_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("pkg");
+        let tarball = craft_tarball(&[
+            Entry::File("package/bin/cli.js", "#!/usr/bin/env node", 0o700),
+            Entry::File("package/secret.txt", "data", 0o600),
+            Entry::File("package/setuid", "data", 0o4777),
+        ]);
+
+        extract::extract_package(&tarball, &dest).unwrap();
+        let mode = |p: &str| std::fs::metadata(dest.join(p)).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode("bin/cli.js"), 0o755);
+        assert_eq!(mode("secret.txt"), 0o644);
+        assert_eq!(mode("setuid"), 0o755);
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests {
+    use crate::http::{get_with, RetryPolicy};
+    use reqwest::Client;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // A tiny HTTP server that answers the n-th request with the n-th scripted
+    // response (the last one repeats). `None` drops the connection unanswered.
+    async fn mock_server(script: Vec<Option<&'static str>>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/pkg", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let reply = script[n.min(script.len() - 1)];
+
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                if let Some(reply) = reply {
+                    let _ = socket.write_all(reply.as_bytes()).await;
+                }
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (url, hits)
+    }
+
+    fn response(status: &str, extra: &str, body: &str) -> String {
+        format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+                status, body.len(), extra, body)
+    }
+
+    fn leak(s: String) -> Option<&'static str> {
+        Some(Box::leak(s.into_boxed_str()))
+    }
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy { retries, min_delay: Duration::from_millis(1), max_delay: Duration::from_millis(5) }
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors_and_rate_limits() {
+        let (url, hits) = mock_server(vec![
+            leak(response("503 Service Unavailable", "", "")),
+            leak(response("429 Too Many Requests", "Retry-After: 0\r\n", "")),
+            leak(response("200 OK", "", "{\"ok\":true}")),
+        ]).await;
+
+        let resp = get_with(&Client::new(), &policy(3), |c| c.get(&url)).await.unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body, b"{\"ok\":true}");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries_dropped_connections() {
+        let (url, hits) = mock_server(vec![
+            None,
+            leak(response("200 OK", "", "done")),
+        ]).await;
+
+        let resp = get_with(&Client::new(), &policy(2), |c| c.get(&url)).await.unwrap();
+        assert_eq!(resp.body, b"done");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, hits) = mock_server(vec![leak(response("404 Not Found", "", ""))]).await;
+
+        let resp = get_with(&Client::new(), &policy(3), |c| c.get(&url)).await.unwrap();
+        assert_eq!(resp.status, 404);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_configured_retries() {
+        let (url, hits) = mock_server(vec![leak(response("500 Internal Server Error", "", ""))]).await;
+
+        let resp = get_with(&Client::new(), &policy(2), |c| c.get(&url)).await.unwrap();
+        assert_eq!(resp.status, 500);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+}
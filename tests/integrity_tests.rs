@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use crate::cache;
+    use crate::integrity::{Algorithm, Integrity};
+    use crate::registry::NpmDist;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    // Build a small package tarball in memory, laid out the way npm packs them
+    fn craft_tarball(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("package/{}", path), content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn sri(algorithm: Algorithm, name: &str, bytes: &[u8]) -> String {
+        format!("{}-{}", name, STANDARD.encode(algorithm.digest(bytes)))
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_sri_algorithms() {
+        let tarball = craft_tarball(&[("package.json", r#"{"name":"demo","version":"1.0.0"}"#)]);
+        let cases = [
+            (Algorithm::Sha1, "sha1"),
+            (Algorithm::Sha256, "sha256"),
+            (Algorithm::Sha384, "sha384"),
+            (Algorithm::Sha512, "sha512"),
+        ];
+
+        for (algorithm, name) in cases {
+            let integrity = Integrity::parse(&sri(algorithm, name, &tarball)).unwrap();
+            assert!(integrity.check(&tarball).is_ok(), "{} should verify", name);
+            assert!(integrity.check(b"tampered").is_err(), "{} should reject", name);
+        }
+    }
+
+    #[test]
+    fn test_strongest_hash_wins() {
+        let tarball = craft_tarball(&[("index.js", "module.exports = 1")]);
+        let other = craft_tarball(&[("index.js", "module.exports = 2")]);
+
+        // A matching sha1 does not help when the sha512 is wrong
+        let mixed = format!("{} {}", sri(Algorithm::Sha1, "sha1", &tarball), sri(Algorithm::Sha512, "sha512", &other));
+        assert!(Integrity::parse(&mixed).unwrap().check(&tarball).is_err());
+
+        // Unknown algorithms and options are skipped
+        let with_unknown = format!("md5-AAAA {}?opt", sri(Algorithm::Sha512, "sha512", &tarball));
+        assert!(Integrity::parse(&with_unknown).unwrap().check(&tarball).is_ok());
+
+        assert!(Integrity::parse("md5-AAAA").is_err());
+    }
+
+    #[test]
+    fn test_legacy_shasum() {
+        let tarball = craft_tarball(&[("index.js", "console.log('hi')")]);
+        let dist = NpmDist {
+            tarball: "https://registry.example.com/demo/-/demo-1.0.0.tgz".to_string(),
+            shasum: hex(&Algorithm::Sha1.digest(&tarball)),
+            integrity: None,
+        };
+
+        let integrity = Integrity::for_dist(&dist).unwrap().unwrap();
+        assert!(integrity.check(&tarball).is_ok());
+        assert!(integrity.check(&craft_tarball(&[("index.js", "")])).is_err());
+        assert!(Integrity::from_shasum("not-hex").is_err());
+
+        let unverifiable = NpmDist { shasum: String::new(), ..dist };
+        assert!(Integrity::for_dist(&unverifiable).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_corrupt_cache_entry_is_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("demo-1.0.0.tgz");
+        let tarball = craft_tarball(&[("package.json", "{}")]);
+        let integrity = Integrity::parse(&sri(Algorithm::Sha512, "sha512", &tarball)).unwrap();
+
+        cache::write_cached_tarball(&path, &tarball).unwrap();
+        assert_eq!(cache::read_cached_tarball(&path, Some(&integrity)).unwrap(), tarball);
+
+        // Simulate a truncated download sitting in the cache
+        std::fs::write(&path, &tarball[..tarball.len() / 2]).unwrap();
+        assert!(cache::read_cached_tarball(&path, Some(&integrity)).is_none());
+        assert!(!path.exists());
+    }
+}
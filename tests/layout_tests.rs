@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use crate::layout;
+    use crate::lockfile::Lockfile;
+    use crate::registry::NpmDist;
+    use crate::resolver::{ResolvedGraph, ResolvedPackage};
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    // Build a graph from (name@version, [dependency name@version]) entries;
+    // roots are given as name@version
+    fn graph(roots: &[&str], nodes: &[(&str, &[&str])]) -> ResolvedGraph {
+        let split = |key: &str| {
+            let at = key.rfind('@').unwrap();
+            (key[..at].to_string(), key[at + 1..].to_string())
+        };
+
+        let mut graph = ResolvedGraph::default();
+        for (key, deps) in nodes {
+            let (name, version) = split(key);
+            let tarball = format!("https://registry.example/{}/-/{}-{}.tgz", name, name, version);
+            graph.order.push(key.to_string());
+            graph.packages.insert(key.to_string(), ResolvedPackage {
+                name,
+                version,
+                dist: NpmDist { tarball, shasum: String::new(), integrity: None },
+                dependencies: deps.iter().map(|d| split(d)).collect(),
+                optional_dependencies: BTreeMap::new(),
+                peer_dependencies: BTreeMap::new(),
+                peer_dependencies_meta: BTreeMap::new(),
+                os: Vec::new(),
+                cpu: Vec::new(),
+                libc: Vec::new(),
+            });
+        }
+        graph.roots = roots.iter().map(|r| split(r)).collect();
+        graph
+    }
+
+    fn sorted(placement: HashMap<String, String>) -> Vec<(String, String)> {
+        let mut placement: Vec<_> = placement.into_iter().collect();
+        placement.sort();
+        placement
+    }
+
+    fn entry(path: &str, key: &str) -> (String, String) {
+        (path.to_string(), key.to_string())
+    }
+
+    #[test]
+    fn test_shared_versions_are_hoisted() {
+        let graph = graph(&["express@4.0.0"], &[
+            ("express@4.0.0", &["debug@2.6.9", "ms@2.0.0"]),
+            ("debug@2.6.9", &["ms@2.0.0"]),
+            ("ms@2.0.0", &[]),
+        ]);
+
+        assert_eq!(sorted(layout::plan(&graph)), vec![
+            entry("node_modules/debug", "debug@2.6.9"),
+            entry("node_modules/express", "express@4.0.0"),
+            entry("node_modules/ms", "ms@2.0.0"),
+        ]);
+    }
+
+    #[test]
+    fn test_conflicting_versions_are_nested() {
+        // Two packages want ms@2.1.3, one wants ms@2.0.0
+        let graph = graph(&["a@1.0.0", "b@1.0.0", "c@1.0.0"], &[
+            ("a@1.0.0", &["ms@2.0.0"]),
+            ("b@1.0.0", &["ms@2.1.3"]),
+            ("c@1.0.0", &["ms@2.1.3"]),
+            ("ms@2.0.0", &[]),
+            ("ms@2.1.3", &[]),
+        ]);
+
+        let placement = layout::plan(&graph);
+        assert_eq!(placement["node_modules/ms"], "ms@2.1.3");
+        assert_eq!(placement["node_modules/a/node_modules/ms"], "ms@2.0.0");
+        assert_eq!(placement.len(), 5);
+    }
+
+    #[test]
+    fn test_top_level_dependencies_win() {
+        let graph = graph(&["ms@1.0.0", "a@1.0.0", "b@1.0.0"], &[
+            ("ms@1.0.0", &[]),
+            ("a@1.0.0", &["ms@2.1.3"]),
+            ("b@1.0.0", &["ms@2.1.3"]),
+            ("ms@2.1.3", &[]),
+        ]);
+
+        let placement = layout::plan(&graph);
+        assert_eq!(placement["node_modules/ms"], "ms@1.0.0");
+        assert_eq!(placement["node_modules/a/node_modules/ms"], "ms@2.1.3");
+        assert_eq!(placement["node_modules/b/node_modules/ms"], "ms@2.1.3");
+    }
+
+    #[test]
+    fn test_nested_copies_do_not_shadow_other_versions() {
+        // a nests b@2, which wants the hoisted ms@1; a's own ms@2 must not be
+        // what b@2 finds
+        let graph = graph(&["a@1.0.0", "b@1.0.0", "ms@1.0.0"], &[
+            ("a@1.0.0", &["b@2.0.0", "ms@2.0.0"]),
+            ("b@1.0.0", &[]),
+            ("ms@1.0.0", &[]),
+            ("b@2.0.0", &["ms@1.0.0"]),
+            ("ms@2.0.0", &[]),
+        ]);
+
+        let placement = layout::plan(&graph);
+        assert_eq!(placement["node_modules/a/node_modules/b"], "b@2.0.0");
+        assert_eq!(placement["node_modules/a/node_modules/ms"], "ms@2.0.0");
+        assert_eq!(placement["node_modules/a/node_modules/b/node_modules/ms"], "ms@1.0.0");
+    }
+
+    #[test]
+    fn test_cycles_terminate() {
+        let graph = graph(&["a@1.0.0"], &[
+            ("a@1.0.0", &["b@1.0.0"]),
+            ("b@1.0.0", &["a@1.0.0"]),
+        ]);
+        assert_eq!(layout::plan(&graph).len(), 2);
+    }
+
+    #[test]
+    fn test_cycle_between_versions_terminates() {
+        // A@1 -> B@1 -> A@2 -> B@2 -> A@1: every step finds the other version
+        let graph = graph(&["a@1.0.0"], &[
+            ("a@1.0.0", &["b@1.0.0"]),
+            ("b@1.0.0", &["a@2.0.0"]),
+            ("a@2.0.0", &["b@2.0.0"]),
+            ("b@2.0.0", &["a@1.0.0"]),
+        ]);
+
+        assert_eq!(sorted(layout::plan(&graph)), vec![
+            entry("node_modules/a", "a@1.0.0"),
+            entry("node_modules/a/node_modules/b", "b@1.0.0"),
+            entry("node_modules/a/node_modules/b/node_modules/a", "a@2.0.0"),
+            entry("node_modules/a/node_modules/b/node_modules/a/node_modules/b", "b@2.0.0"),
+        ]);
+    }
+
+    #[test]
+    fn test_lock_of_cycle_between_versions_verifies() {
+        let graph = graph(&["a@1.0.0"], &[
+            ("a@1.0.0", &["b@1.0.0"]),
+            ("b@1.0.0", &["a@2.0.0"]),
+            ("a@2.0.0", &["b@2.0.0"]),
+            ("b@2.0.0", &["a@1.0.0"]),
+        ]);
+        let lock = Lockfile::from_graph(&graph, &layout::plan(&graph), &HashSet::from(["a".to_string()]));
+
+        let deps = BTreeMap::from([("a".to_string(), "^1.0.0".to_string())]);
+        assert_eq!(lock.verify(&deps, &BTreeMap::new()), Vec::<String>::new());
+    }
+}
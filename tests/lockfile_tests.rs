@@ -0,0 +1,184 @@
+#[cfg(test)]
+mod tests {
+    use crate::lockfile::{self, LockedPackage, LOCKFILE_VERSION};
+
+    #[test]
+    fn test_migrate_v1_lockfile() {
+        let v1 = r#"{ "dependencies": { "express": "5.1.0", "@types/node": "20.1.0" } }"#;
+        let lock = lockfile::parse_lockfile(v1).unwrap();
+
+        assert_eq!(lock.lockfile_version, LOCKFILE_VERSION);
+        let express = &lock.packages["node_modules/express"];
+        assert_eq!(express.version, "5.1.0");
+        assert!(express.resolved.is_empty());
+        assert!(express.dist().is_none());
+        assert_eq!(lock.packages["node_modules/@types/node"].version, "20.1.0");
+    }
+
+    #[test]
+    fn test_v2_round_trip() {
+        let mut lock = lockfile::Lockfile::default();
+        lock.packages.insert("node_modules/debug".to_string(), LockedPackage {
+            version: "4.3.4".to_string(),
+            resolved: "https://registry.npmjs.org/debug/-/debug-4.3.4.tgz".to_string(),
+            integrity: Some("sha512-abc".to_string()),
+            shasum: Some("0123456789abcdef".to_string()),
+            dependencies: [("ms".to_string(), "2.1.2".to_string())].into_iter().collect(),
+            peer_dependencies: [("supports-color".to_string(), "*".to_string())].into_iter().collect(),
+            dev: true,
+            ..Default::default()
+        });
+
+        let content = serde_json::to_string_pretty(&lock).unwrap();
+        let parsed = lockfile::parse_lockfile(&content).unwrap();
+        assert_eq!(parsed.packages, lock.packages);
+
+        let dist = parsed.packages["node_modules/debug"].dist().unwrap();
+        assert_eq!(dist.tarball, "https://registry.npmjs.org/debug/-/debug-4.3.4.tgz");
+        assert_eq!(dist.integrity.as_deref(), Some("sha512-abc"));
+    }
+
+    #[test]
+    fn test_rejects_newer_lockfile_version() {
+        let content = r#"{ "lockfileVersion": 99, "packages": {} }"#;
+        assert!(lockfile::parse_lockfile(content).is_err());
+    }
+
+    #[test]
+    fn test_package_name_from_install_path() {
+        assert_eq!(lockfile::package_name("node_modules/express"), "express");
+        assert_eq!(lockfile::package_name("node_modules/a/node_modules/@scope/b"), "@scope/b");
+    }
+
+    #[test]
+    fn test_merge_conflicted_lockfile() {
+        let content = r#"{
+  "lockfileVersion": 2,
+  "packages": {
+<<<<<<< HEAD
+    "node_modules/debug": {
+      "version": "4.3.4"
+    },
+=======
+    "node_modules/debug": {
+      "version": "4.3.5"
+    },
+    "node_modules/ms": {
+      "version": "2.1.3"
+    },
+>>>>>>> feature
+    "node_modules/express": {
+      "version": "5.1.0"
+    }
+  }
+}
+"#;
+        let (ours, theirs) = lockfile::split_conflict(content).unwrap();
+        assert!(ours.contains("4.3.4") && !ours.contains("4.3.5"));
+        assert!(theirs.contains("4.3.5") && !theirs.contains("4.3.4"));
+
+        let lock = lockfile::parse_lockfile(content).unwrap();
+        assert_eq!(lock.packages["node_modules/debug"].version, "4.3.5");
+        assert_eq!(lock.packages["node_modules/ms"].version, "2.1.3");
+        assert_eq!(lock.packages["node_modules/express"].version, "5.1.0");
+    }
+
+    #[test]
+    fn test_serialization_is_sorted() {
+        let mut lock = lockfile::Lockfile::default();
+        for name in ["zod", "express", "@types/node", "debug"] {
+            lock.packages.insert(lockfile::install_path(name), LockedPackage {
+                version: "1.0.0".to_string(),
+                ..Default::default()
+            });
+        }
+
+        let content = serde_json::to_string_pretty(&lock).unwrap();
+        let positions: Vec<usize> = ["@types/node", "debug", "express", "zod"].iter()
+            .map(|name| content.find(&format!("node_modules/{}", name)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    fn locked(version: &str, deps: &[(&str, &str)]) -> LockedPackage {
+        LockedPackage {
+            version: version.to_string(),
+            resolved: format!("https://registry.example.com/-/pkg-{}.tgz", version),
+            dependencies: deps.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn manifest_deps(deps: &[(&str, &str)]) -> std::collections::BTreeMap<String, String> {
+        deps.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect()
+    }
+
+    #[test]
+    fn test_find_dependency_walks_up() {
+        let mut lock = lockfile::Lockfile::default();
+        lock.packages.insert("node_modules/ms".to_string(), locked("2.1.3", &[]));
+        lock.packages.insert("node_modules/debug/node_modules/ms".to_string(), locked("2.0.0", &[]));
+
+        let (path, pkg) = lock.find_dependency("node_modules/debug", "ms").unwrap();
+        assert_eq!(path, "node_modules/debug/node_modules/ms");
+        assert_eq!(pkg.version, "2.0.0");
+
+        let (path, _) = lock.find_dependency("node_modules/express/node_modules/@scope/x", "ms").unwrap();
+        assert_eq!(path, "node_modules/ms");
+        assert!(lock.find_dependency("", "missing").is_none());
+    }
+
+    #[test]
+    fn test_verify_up_to_date_lockfile() {
+        let mut lock = lockfile::Lockfile::default();
+        lock.packages.insert("node_modules/debug".to_string(), locked("4.3.4", &[("ms", "2.1.2")]));
+        lock.packages.insert("node_modules/ms".to_string(), locked("2.1.2", &[]));
+
+        assert!(lock.verify(&manifest_deps(&[("debug", "^4.3.0")]), &manifest_deps(&[])).is_empty());
+        // Dist-tags can't be checked and are accepted
+        assert!(lock.verify(&manifest_deps(&[("debug", "latest")]), &manifest_deps(&[("fsevents", "^2.0.0")])).is_empty());
+    }
+
+    #[test]
+    fn test_verify_reports_every_problem() {
+        let mut lock = lockfile::Lockfile::default();
+        lock.packages.insert("node_modules/debug".to_string(), locked("4.3.4", &[("ms", "2.1.2")]));
+        lock.packages.insert("node_modules/ms".to_string(), locked("2.1.3", &[]));
+        lock.packages.insert("node_modules/left-pad".to_string(), locked("1.3.0", &[]));
+        lock.packages.insert("node_modules/zod".to_string(), LockedPackage {
+            version: "3.0.0".to_string(),
+            ..Default::default()
+        });
+
+        let problems = lock.verify(&manifest_deps(&[
+            ("debug", "^5.0.0"),
+            ("express", "^5.0.0"),
+            ("zod", "^3.0.0"),
+        ]), &manifest_deps(&[]));
+        let has = |text: &str| problems.iter().any(|p| p.contains(text));
+
+        assert!(has("debug is locked at 4.3.4, which does not satisfy ^5.0.0"));
+        assert!(has("express is missing"));
+        assert!(has("node_modules/debug needs ms@2.1.2, but 2.1.3 is locked"));
+        assert!(has("node_modules/left-pad is locked but no longer depended on"));
+        assert!(has("node_modules/zod has no tarball information"));
+        assert_eq!(problems.len(), 5);
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let mut lock = lockfile::Lockfile::default();
+        lock.packages.insert("node_modules/app".to_string(), locked("1.0.0", &[("esbuild", "0.19.0"), ("ms", "2.0.0")]));
+        lock.packages.insert("node_modules/app/node_modules/ms".to_string(), locked("2.0.0", &[]));
+        lock.packages.insert("node_modules/esbuild".to_string(), locked("0.19.0", &[("ms", "2.1.3")]));
+        lock.packages.insert("node_modules/ms".to_string(), locked("2.1.3", &[("esbuild", "0.19.0")]));
+
+        let order = lock.dependency_order();
+        let position = |path: &str| order.iter().position(|p| p == path).unwrap();
+        assert_eq!(order.len(), 4);
+        assert!(position("node_modules/app/node_modules/ms") < position("node_modules/app"));
+        assert!(position("node_modules/esbuild") < position("node_modules/app"));
+        // esbuild and ms depend on each other; both still appear once
+        assert!(position("node_modules/ms") < position("node_modules/app"));
+    }
+}
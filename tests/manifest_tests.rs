@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use crate::manifest;
+
+    #[test]
+    fn test_reads_package_json() {
+        let content = r#"{
+  "name": "web",
+  "version": "1.2.0",
+  "private": true,
+  "author": { "name": "Jane", "email": "jane@example.com" },
+  "main": "index.js",
+  "dependencies": { "express": "^5.1.0" },
+  "devDependencies": { "typescript": "~5.4.0" },
+  "engines": { "node": ">=18" }
+}"#;
+        let manifest = manifest::parse_manifest(content).unwrap();
+
+        assert_eq!(manifest.name, "web");
+        assert_eq!(manifest.dependencies["express"], "^5.1.0");
+        assert_eq!(manifest.dev_dependencies["typescript"], "~5.4.0");
+        assert!(manifest.scripts.is_empty());
+        assert_eq!(manifest.other["main"], "index.js");
+    }
+
+    #[test]
+    fn test_writes_standard_fields_and_keeps_unknown_ones() {
+        let content = r#"{ "name": "web", "private": true, "engines": { "node": ">=18" } }"#;
+        let mut manifest = manifest::parse_manifest(content).unwrap();
+        manifest.dev_dependencies.insert("typescript".to_string(), "5.4.5".to_string());
+
+        let written = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(written["devDependencies"]["typescript"], "5.4.5");
+        assert_eq!(written["private"], true);
+        assert_eq!(written["engines"]["node"], ">=18");
+        // Empty sections and unset fields are left out, like npm does
+        assert!(written.get("dev_dependencies").is_none());
+        assert!(written.get("dependencies").is_none());
+        assert!(written.get("description").is_none());
+    }
+
+    #[test]
+    fn test_reads_legacy_a_json() {
+        let content = r#"{
+  "name": "old",
+  "version": "0.1.0",
+  "description": null,
+  "author": null,
+  "dependencies": {},
+  "dev_dependencies": { "jest": "29.7.0" },
+  "scripts": {}
+}"#;
+        let manifest = manifest::parse_manifest(content).unwrap();
+        assert_eq!(manifest.dev_dependencies["jest"], "29.7.0");
+        assert!(manifest.other.is_empty());
+    }
+
+    #[test]
+    fn test_edits_keep_everything_else() {
+        let content = "{\n    \"name\": \"web\",\n    \"type\": \"module\",\n    \"exports\": { \"./\": \"./src/\" },\n    \"files\": [\"dist\", \"src\"],\n    \"dependencies\": {\n        \"zod\": \"^3.0.0\"\n    },\n    \"keywords\": [ \"a,b\", \"}\" ],\n    \"private\": true\n}";
+        let mut doc = manifest::ManifestDocument::parse(content).unwrap();
+        doc.set_dependency("dependencies", "express", "5.1.0");
+
+        // Only the edited section is written again; the original had no
+        // trailing newline
+        assert_eq!(doc.render().unwrap(), "{\n    \"name\": \"web\",\n    \"type\": \"module\",\n    \"exports\": { \"./\": \"./src/\" },\n    \"files\": [\"dist\", \"src\"],\n    \"dependencies\": {\n        \"express\": \"5.1.0\",\n        \"zod\": \"^3.0.0\"\n    },\n    \"keywords\": [ \"a,b\", \"}\" ],\n    \"private\": true\n}");
+
+        // Without edits nothing changes at all
+        let doc = manifest::ManifestDocument::parse(content).unwrap();
+        assert_eq!(doc.render().unwrap(), content);
+    }
+
+    #[test]
+    fn test_edit_adds_missing_section_at_the_end() {
+        let content = "{\r\n\t\"name\": \"web\",\r\n\t\"scripts\": {}\r\n}\r\n";
+        let mut doc = manifest::ManifestDocument::parse(content).unwrap();
+        doc.set_dependency("devDependencies", "typescript", "5.4.5");
+
+        assert_eq!(doc.render().unwrap(),
+                   "{\r\n\t\"name\": \"web\",\r\n\t\"scripts\": {},\r\n\t\"devDependencies\": {\r\n\t\t\"typescript\": \"5.4.5\"\r\n\t}\r\n}\r\n");
+    }
+
+    #[test]
+    fn test_remove_dependency_only_touches_its_section() {
+        let content = "{\n  \"dependencies\": { \"a\": \"1\", \"b\": \"2\" },\n  \"devDependencies\": { \"a\": \"1\" }\n}\n";
+        let mut doc = manifest::ManifestDocument::parse(content).unwrap();
+
+        assert!(doc.remove_dependency("dependencies", "a"));
+        assert!(!doc.remove_dependency("dependencies", "missing"));
+        let written: serde_json::Value = serde_json::from_str(&doc.render().unwrap()).unwrap();
+        assert_eq!(written["dependencies"], serde_json::json!({ "b": "2" }));
+        assert_eq!(written["devDependencies"], serde_json::json!({ "a": "1" }));
+    }
+
+    #[test]
+    fn test_dev_edits_of_legacy_a_json() {
+        let content = "{\n  \"name\": \"old\",\n  \"dev_dependencies\": { \"jest\": \"29.7.0\" },\n  \"scripts\": {}\n}\n";
+        let mut doc = manifest::ManifestDocument::parse(content).unwrap();
+        doc.set_dependency("devDependencies", "typescript", "^5.4.5");
+        let written = doc.render().unwrap();
+
+        // The section is renamed where it was, not duplicated
+        assert!(written.starts_with("{\n  \"name\": \"old\",\n  \"devDependencies\": {"));
+        assert!(!written.contains("dev_dependencies"));
+        let manifest = manifest::parse_manifest(&written).unwrap();
+        assert_eq!(manifest.dev_dependencies.len(), 2);
+
+        let mut doc = manifest::ManifestDocument::parse(content).unwrap();
+        assert!(doc.remove_dependency("devDependencies", "jest"));
+        let manifest = manifest::parse_manifest(&doc.render().unwrap()).unwrap();
+        assert!(manifest.dev_dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_section_is_merged_into_the_standard_one() {
+        let content = "{\n  \"dev_dependencies\": { \"jest\": \"29.7.0\" },\n  \"devDependencies\": { \"jest\": \"^29.0.0\" },\n  \"files\": [ \"lib\" ]\n}\n";
+        let mut doc = manifest::ManifestDocument::parse(content).unwrap();
+        doc.set_dependency("dependencies", "zod", "^3.0.0");
+
+        assert_eq!(doc.render().unwrap(), "{\n  \"devDependencies\": {\n    \"jest\": \"^29.0.0\"\n  },\n  \"files\": [ \"lib\" ],\n  \"dependencies\": {\n    \"zod\": \"^3.0.0\"\n  }\n}\n");
+    }
+
+    #[test]
+    fn test_edit_of_empty_manifest() {
+        let mut doc = manifest::ManifestDocument::parse("{}\n").unwrap();
+        doc.set_dependency("dependencies", "zod", "^3.0.0");
+        assert_eq!(doc.render().unwrap(), "{\n  \"dependencies\": {\n    \"zod\": \"^3.0.0\"\n  }\n}\n");
+    }
+}
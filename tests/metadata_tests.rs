@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use crate::cache::NotCached;
+    use crate::config::NetworkMode;
+    use crate::metadata::{MetadataCache, ABBREVIATED};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const DOC: &str = "{\"dist-tags\":{\"latest\":\"1.0.0\"},\"versions\":{}}";
+
+    // Serves DOC with an ETag, or a 304 when the request carries that ETag.
+    // Returns the URL and the raw text of every request received.
+    async fn registry() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/left-pad", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let mut buf = [0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+
+                let reply = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    format!("HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            DOC.len(), DOC)
+                };
+                seen.lock().unwrap().push(request);
+                let _ = socket.write_all(reply.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_fresh_entries_skip_the_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = registry().await;
+        let cache = MetadataCache { dir: dir.path().to_path_buf(), max_age: Duration::from_secs(300), mode: NetworkMode::Online };
+
+        let first = cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+        let second = cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+
+        assert_eq!(first.body, DOC.as_bytes());
+        assert_eq!(second.body, DOC.as_bytes());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].contains("accept: application/vnd.npm.install-v1+json"));
+    }
+
+    #[tokio::test]
+    async fn test_stale_entries_are_revalidated() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = registry().await;
+        let cache = MetadataCache { dir: dir.path().to_path_buf(), max_age: Duration::ZERO, mode: NetworkMode::Online };
+
+        cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+        let revalidated = cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+
+        // The 304 is answered from the cache
+        assert_eq!(revalidated.status, 200);
+        assert_eq!(revalidated.body, DOC.as_bytes());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+
+    #[tokio::test]
+    async fn test_formats_are_cached_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = registry().await;
+        let cache = MetadataCache { dir: dir.path().to_path_buf(), max_age: Duration::from_secs(300), mode: NetworkMode::Online };
+
+        cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+        cache.get(&url, "application/json", |c| c.get(&url)).await.unwrap();
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_offline_modes_use_stale_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = registry().await;
+        let mut cache = MetadataCache { dir: dir.path().to_path_buf(), max_age: Duration::ZERO, mode: NetworkMode::Online };
+        cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+
+        for mode in [NetworkMode::PreferOffline, NetworkMode::Offline] {
+            cache.mode = mode;
+            let resp = cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap();
+            assert_eq!(resp.body, DOC.as_bytes());
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_offline_miss_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = registry().await;
+        let cache = MetadataCache { dir: dir.path().to_path_buf(), max_age: Duration::ZERO, mode: NetworkMode::Offline };
+
+        let err = cache.get(&url, ABBREVIATED, |c| c.get(&url)).await.unwrap_err();
+        assert!(err.is::<NotCached>());
+        assert!(requests.lock().unwrap().is_empty());
+    }
+}
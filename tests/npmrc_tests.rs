@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::npmrc::{self, Npmrc};
+
+    const NPMRC: &str = r#"
+# Company registry
+registry=https://npm.ourco.example/
+@ourco:registry=https://npm.pkg.ourco.example/npm/
+//npm.pkg.ourco.example/npm/:_authToken=${NPM_TOKEN}
+//npm.ourco.example/:_auth="dXNlcjpwYXNz"
+; legacy style credentials
+//legacy.example/:username=bob
+//legacy.example/:_password=c2VjcmV0
+"#;
+
+    fn load(content: &str) -> Npmrc {
+        let mut npmrc = Npmrc::default();
+        npmrc.merge(content, |key| match key {
+            "NPM_TOKEN" => Some("s3cr3t".to_string()),
+            _ => None,
+        }).unwrap();
+        npmrc
+    }
+
+    #[test]
+    fn test_parse_npmrc() {
+        let npmrc = load(NPMRC);
+        assert_eq!(npmrc.registry.as_deref(), Some("https://npm.ourco.example"));
+        assert_eq!(npmrc.scopes["@ourco"], "https://npm.pkg.ourco.example/npm");
+        assert!(!npmrc.always_auth);
+
+        let scoped = npmrc.credentials_for("https://npm.pkg.ourco.example/npm/@ourco%2fui").unwrap();
+        assert_eq!(scoped.header().unwrap(), "Bearer s3cr3t");
+
+        let basic = npmrc.credentials_for("https://npm.ourco.example/lodash").unwrap();
+        assert_eq!(basic.header().unwrap(), "Basic dXNlcjpwYXNz");
+
+        let legacy = npmrc.credentials_for("https://legacy.example/pkg").unwrap();
+        assert_eq!(legacy.header().unwrap(), "Basic Ym9iOnNlY3JldA==");
+
+        assert!(npmrc.credentials_for("https://registry.npmjs.org/lodash").is_none());
+    }
+
+    #[test]
+    fn test_unset_variable_is_an_error() {
+        let mut npmrc = Npmrc::default();
+        let err = npmrc.merge(NPMRC, |_| None).unwrap_err();
+        assert!(err.to_string().contains("${NPM_TOKEN}"), "{}", err);
+        assert!(!npmrc.credentials.contains_key("//npm.pkg.ourco.example/npm/"));
+    }
+
+    #[test]
+    fn test_routes_packages_to_registries() {
+        let config = Config::from_sources(|_| None, load(NPMRC)).unwrap();
+        assert_eq!(config.registry_for("@ourco/ui"), "https://npm.pkg.ourco.example/npm");
+        assert_eq!(config.registry_for("@other/ui"), "https://npm.ourco.example");
+        assert_eq!(config.registry_for("lodash"), "https://npm.ourco.example");
+
+        // A_REGISTRY still overrides the .npmrc default registry
+        let config = Config::from_sources(
+            |key| (key == "A_REGISTRY").then(|| "https://mirror.example".to_string()),
+            load(NPMRC),
+        ).unwrap();
+        assert_eq!(config.registry_for("lodash"), "https://mirror.example");
+        assert_eq!(config.registry_for("@ourco/ui"), "https://npm.pkg.ourco.example/npm");
+    }
+
+    #[test]
+    fn test_always_auth_sends_registry_credentials_to_other_hosts() {
+        let tarball = "https://cdn.ourco.example/ui/-/ui-1.0.0.tgz";
+        let registry = "https://npm.pkg.ourco.example/npm";
+
+        let config = Config::from_sources(|_| None, load(NPMRC)).unwrap();
+        assert_eq!(config.auth_header(tarball, registry), None);
+
+        let config = Config::from_sources(|_| None, load(&format!("{}\nalways-auth=true", NPMRC))).unwrap();
+        assert_eq!(config.auth_header(tarball, registry).as_deref(), Some("Bearer s3cr3t"));
+    }
+
+    #[test]
+    fn test_nerf_dart() {
+        assert_eq!(npmrc::nerf_dart("https://registry.example.com:8443/npm/pkg?x=1").unwrap(),
+                   "//registry.example.com:8443/npm/pkg/");
+        assert_eq!(npmrc::nerf_dart("http://localhost:4873").unwrap(), "//localhost:4873/");
+    }
+}
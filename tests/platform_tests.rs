@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use crate::lockfile::LockedPackage;
+    use crate::platform::Platform;
+
+    fn list(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn linux(libc: &str) -> Platform {
+        Platform { os: "linux".to_string(), cpu: "x64".to_string(), libc: Some(libc.to_string()) }
+    }
+
+    #[test]
+    fn test_allow_and_block_lists() {
+        let platform = linux("glibc");
+
+        assert!(platform.supports(&[], &[], &[]));
+        assert!(platform.supports(&list(&["linux", "darwin"]), &list(&["x64"]), &[]));
+        assert!(!platform.supports(&list(&["darwin"]), &[], &[]));
+        assert!(!platform.supports(&[], &list(&["arm64"]), &[]));
+        assert!(platform.supports(&list(&["!win32"]), &list(&["!arm"]), &[]));
+        assert!(!platform.supports(&list(&["!linux"]), &[], &[]));
+    }
+
+    #[test]
+    fn test_libc_only_applies_on_linux() {
+        assert!(linux("glibc").supports(&[], &[], &list(&["glibc"])));
+        assert!(!linux("musl").supports(&[], &[], &list(&["glibc"])));
+
+        let mac = Platform { os: "darwin".to_string(), cpu: "arm64".to_string(), libc: None };
+        assert!(mac.supports(&list(&["darwin"]), &list(&["arm64"]), &list(&["glibc"])));
+        assert_eq!(mac.to_string(), "darwin arm64");
+        assert_eq!(linux("musl").to_string(), "linux x64 musl");
+    }
+
+    #[test]
+    fn test_current_platform_uses_node_names() {
+        let platform = Platform::current();
+        assert!(!["macos", "windows", "x86_64", "aarch64"].contains(&platform.os.as_str()));
+        assert!(!["x86_64", "aarch64"].contains(&platform.cpu.as_str()));
+        assert_eq!(platform.libc.is_some(), platform.os == "linux");
+    }
+
+    #[test]
+    fn test_locked_platform_variants() {
+        let esbuild_variant = |os: &str, cpu: &str| LockedPackage {
+            version: "0.20.2".to_string(),
+            os: list(&[os]),
+            cpu: list(&[cpu]),
+            optional: true,
+            ..Default::default()
+        };
+        let platform = linux("glibc");
+
+        assert!(esbuild_variant("linux", "x64").supports(&platform));
+        assert!(!esbuild_variant("darwin", "arm64").supports(&platform));
+        assert!(!esbuild_variant("linux", "arm64").supports(&platform));
+    }
+}
@@ -0,0 +1,259 @@
+#[cfg(test)]
+mod tests {
+    use crate::lockfile::{self, Lockfile};
+    use crate::registry::{NpmDist, NpmResponse, PeerDependencyMeta};
+    use crate::resolver::{self, ResolvedGraph, ResolvedPackage};
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    fn node(name: &str, version: &str, peers: &[(&str, &str)]) -> ResolvedPackage {
+        ResolvedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            dist: NpmDist { tarball: String::new(), shasum: String::new(), integrity: None },
+            dependencies: BTreeMap::new(),
+            optional_dependencies: BTreeMap::new(),
+            peer_dependencies: peers.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect(),
+            peer_dependencies_meta: BTreeMap::new(),
+            os: Vec::new(),
+            cpu: Vec::new(),
+            libc: Vec::new(),
+        }
+    }
+
+    fn graph(nodes: Vec<ResolvedPackage>) -> ResolvedGraph {
+        let mut graph = ResolvedGraph::default();
+        for node in nodes {
+            let key = resolver::node_key(&node.name, &node.version);
+            graph.order.push(key.clone());
+            graph.packages.insert(key, node);
+        }
+        graph
+    }
+
+    // Install path -> node key
+    fn placement(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(path, key)| (path.to_string(), key.to_string())).collect()
+    }
+
+    fn packument(versions: &[&str]) -> NpmResponse {
+        let versions: serde_json::Map<String, serde_json::Value> = versions.iter()
+            .map(|v| (v.to_string(), serde_json::json!({ "dist": { "tarball": "" } })))
+            .collect();
+        serde_json::from_value(serde_json::json!({ "dist-tags": {}, "versions": versions })).unwrap()
+    }
+
+    // An in-memory registry: package -> versions with their dependencies
+    type Registry = HashMap<&'static str, Vec<(&'static str, Vec<(&'static str, &'static str)>)>>;
+
+    // Resolve against `registry`, counting the documents fetched per package
+    async fn resolve(
+        registry: &Registry,
+        deps: &[(&str, &str)],
+        optional_deps: &[(&str, &str)],
+        preferred: &[(&str, &str)],
+    ) -> (anyhow::Result<ResolvedGraph>, HashMap<String, usize>) {
+        let deps = deps.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect();
+        let optional_deps = optional_deps.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect();
+        let preferred = preferred.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+        let fetches = std::sync::Mutex::new(HashMap::new());
+        let graph = resolver::resolve_with(&deps, &optional_deps, &preferred, |name: String| {
+            *fetches.lock().unwrap().entry(name.clone()).or_insert(0) += 1;
+            let versions = registry.get(name.as_str()).cloned();
+            async move {
+                let versions = versions.ok_or_else(|| anyhow::anyhow!("404 Not Found: {}", name))?;
+                let versions: serde_json::Map<String, serde_json::Value> = versions.into_iter()
+                    .map(|(version, deps)| {
+                        let deps: BTreeMap<&str, &str> = deps.into_iter().collect();
+                        (version.to_string(), serde_json::json!({ "dist": { "tarball": "" }, "dependencies": deps }))
+                    })
+                    .collect();
+                Ok(serde_json::from_value(serde_json::json!({ "dist-tags": {}, "versions": versions }))?)
+            }
+        }).await;
+        (graph, fetches.into_inner().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_walks_dependencies_breadth_first() {
+        let registry = Registry::from([
+            ("app-a", vec![("1.0.0", vec![("shared", "^1.0.0")])]),
+            ("app-b", vec![("1.0.0", vec![("shared", "^1.1.0")])]),
+            ("shared", vec![("1.0.0", vec![]), ("1.2.0", vec![("leaf", "~2.0.0")])]),
+            ("leaf", vec![("2.0.0", vec![]), ("2.0.5", vec![]), ("2.1.0", vec![])]),
+        ]);
+        let (graph, fetches) = resolve(&registry, &[("app-a", "^1.0.0"), ("app-b", "1.0.0")], &[], &[]).await;
+        let graph = graph.unwrap();
+
+        assert_eq!(graph.roots.get("app-a").map(String::as_str), Some("1.0.0"));
+        assert_eq!(graph.roots.get("app-b").map(String::as_str), Some("1.0.0"));
+        // Both dependents share the highest matching version, fetched once
+        assert_eq!(graph.order, vec!["app-a@1.0.0", "app-b@1.0.0", "shared@1.2.0", "leaf@2.0.5"]);
+        assert_eq!(fetches.get("shared"), Some(&1));
+        assert_eq!(graph.packages["app-a@1.0.0"].dependencies.get("shared").map(String::as_str), Some("1.2.0"));
+        assert_eq!(graph.packages["app-b@1.0.0"].dependencies.get("shared").map(String::as_str), Some("1.2.0"));
+        assert_eq!(graph.packages["shared@1.2.0"].dependencies.get("leaf").map(String::as_str), Some("2.0.5"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_keeps_conflicting_versions_apart() {
+        let registry = Registry::from([
+            ("app-a", vec![("1.0.0", vec![("shared", "^2.0.0")])]),
+            ("shared", vec![("1.0.0", vec![]), ("2.0.0", vec![])]),
+        ]);
+        let (graph, _) = resolve(&registry, &[("app-a", "1.0.0"), ("shared", "^1.0.0")], &[], &[]).await;
+        let graph = graph.unwrap();
+
+        assert_eq!(graph.roots.get("shared").map(String::as_str), Some("1.0.0"));
+        assert_eq!(graph.packages["app-a@1.0.0"].dependencies.get("shared").map(String::as_str), Some("2.0.0"));
+        assert!(graph.packages.contains_key("shared@1.0.0") && graph.packages.contains_key("shared@2.0.0"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_prefers_locked_versions_in_range() {
+        let registry = Registry::from([
+            ("app-a", vec![("1.0.0", vec![]), ("1.1.0", vec![])]),
+            ("shared", vec![("1.0.0", vec![]), ("1.2.0", vec![])]),
+        ]);
+        // The locked app-a still matches; the locked shared no longer does
+        let (graph, _) = resolve(&registry, &[("app-a", "^1.0.0"), ("shared", "^1.1.0")], &[],
+                                 &[("app-a", "1.0.0"), ("shared", "1.0.0")]).await;
+        let graph = graph.unwrap();
+
+        assert_eq!(graph.roots.get("app-a").map(String::as_str), Some("1.0.0"));
+        assert_eq!(graph.roots.get("shared").map(String::as_str), Some("1.2.0"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_fails_on_missing_dependency() {
+        let registry = Registry::from([("app-a", vec![("1.0.0", vec![("gone", "^1.0.0")])])]);
+        let (graph, _) = resolve(&registry, &[("app-a", "^1.0.0")], &[], &[]).await;
+
+        assert!(graph.unwrap_err().to_string().contains("gone"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_skips_missing_dependency_of_optional_package() {
+        let registry = Registry::from([
+            ("app-a", vec![("1.0.0", vec![])]),
+            ("native", vec![("1.0.0", vec![("prebuilt", "^1.0.0"), ("gone", "^1.0.0")])]),
+            ("prebuilt", vec![("1.0.0", vec![])]),
+        ]);
+        let (graph, _) = resolve(&registry, &[("app-a", "^1.0.0")], &[("native", "^1.0.0")], &[]).await;
+        let graph = graph.unwrap();
+
+        assert!(graph.optional_roots.contains("native"));
+        let native = &graph.packages["native@1.0.0"];
+        // Still a regular dependency of its parent
+        assert_eq!(native.dependencies.get("prebuilt").map(String::as_str), Some("1.0.0"));
+        assert!(!native.dependencies.contains_key("gone"));
+    }
+
+    #[test]
+    fn test_missing_peers() {
+        let mut plugin = node("eslint-plugin-x", "1.0.0", &[("eslint", "^8.0.0"), ("typescript", "*")]);
+        plugin.peer_dependencies_meta.insert("typescript".to_string(), PeerDependencyMeta { optional: true });
+        let graph = graph(vec![
+            plugin,
+            node("eslint-config-y", "2.0.0", &[("eslint", ">=8.10.0")]),
+            node("react-dom", "18.2.0", &[("react", "^18.2.0")]),
+            node("react", "17.0.2", &[]),
+        ]);
+
+        let missing = resolver::missing_peers(&graph);
+        // react is present (in the wrong version) and typescript is optional
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing["eslint"], vec!["^8.0.0".to_string(), ">=8.10.0".to_string()]);
+    }
+
+    #[test]
+    fn test_peer_version_satisfies_every_range() {
+        let resp = packument(&["8.0.0", "8.9.0", "8.12.0", "9.0.0"]);
+        let ranges = |r: &[&str]| r.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(resolver::peer_version(&resp, &ranges(&["^8.0.0", "<8.10.0"])).as_deref(), Some("8.9.0"));
+        // No common version: the first range wins
+        assert_eq!(resolver::peer_version(&resp, &ranges(&["^8.0.0", "^9.0.0"])).as_deref(), Some("8.12.0"));
+        assert_eq!(resolver::peer_version(&resp, &ranges(&["^10.0.0"])), None);
+    }
+
+    #[test]
+    fn test_peer_conflicts() {
+        let graph = graph(vec![
+            node("react-dom", "18.2.0", &[("react", "^18.2.0")]),
+            node("react-redux", "8.0.0", &[("react", "^16.8 || ^17 || ^18"), ("redux", "^4")]),
+            node("react", "17.0.2", &[]),
+            // Not installed, so its peers don't matter
+            node("react-dom", "16.0.0", &[("react", "^16.0.0")]),
+        ]);
+        let placement = placement(&[
+            ("node_modules/react-dom", "react-dom@18.2.0"),
+            ("node_modules/react-redux", "react-redux@8.0.0"),
+            ("node_modules/react", "react@17.0.2"),
+        ]);
+
+        let conflicts = resolver::peer_conflicts(&graph, &placement);
+        assert_eq!(conflicts.len(), 2);
+
+        assert_eq!(conflicts[0].peer, "react");
+        assert_eq!(conflicts[0].installed.as_deref(), Some("17.0.2"));
+        assert_eq!(conflicts[0].wanted_by, vec![("react-dom@18.2.0".to_string(), "^18.2.0".to_string())]);
+
+        assert_eq!(conflicts[1].peer, "redux");
+        assert_eq!(conflicts[1].installed, None);
+    }
+
+    #[test]
+    fn test_nested_peer_conflicts() {
+        let graph = graph(vec![
+            node("app-ui", "1.0.0", &[]),
+            node("react-dom", "18.2.0", &[("react", "^18.2.0")]),
+            node("react", "18.2.0", &[]),
+            node("react", "16.14.0", &[]),
+        ]);
+        // The nested react-dom finds the nested react 16, not the top-level 18
+        let placement = placement(&[
+            ("node_modules/app-ui", "app-ui@1.0.0"),
+            ("node_modules/app-ui/node_modules/react", "react@16.14.0"),
+            ("node_modules/app-ui/node_modules/react-dom", "react-dom@18.2.0"),
+            ("node_modules/react", "react@18.2.0"),
+            ("node_modules/react-dom", "react-dom@18.2.0"),
+        ]);
+
+        let conflicts = resolver::peer_conflicts(&graph, &placement);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].peer, "react");
+        assert_eq!(conflicts[0].installed.as_deref(), Some("16.14.0"));
+        assert_eq!(conflicts[0].wanted_by, vec![("react-dom@18.2.0".to_string(), "^18.2.0".to_string())]);
+    }
+
+    #[test]
+    fn test_lockfile_marks_optional_packages() {
+        let mut esbuild = node("esbuild", "0.20.2", &[]);
+        esbuild.dependencies.insert("semver".to_string(), "7.6.0".to_string());
+        esbuild.optional_dependencies.insert("@esbuild/darwin-arm64".to_string(), "0.20.2".to_string());
+        esbuild.optional_dependencies.insert("semver".to_string(), "7.6.0".to_string());
+        let mut darwin = node("@esbuild/darwin-arm64", "0.20.2", &[]);
+        darwin.os = vec!["darwin".to_string()];
+        darwin.cpu = vec!["arm64".to_string()];
+
+        let mut graph = graph(vec![esbuild, darwin, node("semver", "7.6.0", &[]), node("fsevents", "2.3.3", &[])]);
+        graph.roots.insert("esbuild".to_string(), "0.20.2".to_string());
+        graph.roots.insert("fsevents".to_string(), "2.3.3".to_string());
+        graph.optional_roots.insert("fsevents".to_string());
+
+        let placement: HashMap<String, String> = graph.packages.values()
+            .map(|n| (lockfile::install_path(&n.name), resolver::node_key(&n.name, &n.version)))
+            .collect();
+        let prod: HashSet<String> = ["esbuild", "fsevents"].iter().map(|s| s.to_string()).collect();
+        let lock = Lockfile::from_graph(&graph, &placement, &prod);
+
+        assert!(!lock.packages["node_modules/esbuild"].optional);
+        // Also a regular dependency, so required
+        assert!(!lock.packages["node_modules/semver"].optional);
+        assert!(lock.packages["node_modules/fsevents"].optional);
+
+        let darwin = &lock.packages["node_modules/@esbuild/darwin-arm64"];
+        assert!(darwin.optional && !darwin.dev);
+        assert_eq!(darwin.os, vec!["darwin".to_string()]);
+    }
+}
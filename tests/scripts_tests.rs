@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use crate::scripts;
+    use serde_json::json;
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_package_env() {
+        let env = scripts::package_env(&json!({
+            "name": "my-app",
+            "version": "1.2.0",
+            "private": true,
+            "scripts": { "build": "vite build" },
+            "dependencies": { "@scope/pkg": "^1.0.0" },
+            "files": ["dist", "lib"],
+            "readme": "# skipped",
+            "_id": "skipped",
+            "license": null
+        }));
+        let get = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+        assert_eq!(get("npm_package_name"), Some("my-app"));
+        assert_eq!(get("npm_package_version"), Some("1.2.0"));
+        assert_eq!(get("npm_package_private"), Some("true"));
+        assert_eq!(get("npm_package_scripts_build"), Some("vite build"));
+        assert_eq!(get("npm_package_dependencies__scope_pkg"), Some("^1.0.0"));
+        assert_eq!(get("npm_package_files_1"), Some("lib"));
+        assert_eq!(get("npm_package_readme"), None);
+        assert_eq!(get("npm_package__id"), None);
+        assert_eq!(get("npm_package_license"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bin_path() {
+        let existing = std::env::join_paths(["/usr/bin", "/bin"]).unwrap();
+        let path = scripts::bin_path(Path::new("/work/repo/app"), Some(&existing)).unwrap();
+        let entries: Vec<PathBuf> = std::env::split_paths(&path).collect();
+
+        assert_eq!(entries, vec![
+            PathBuf::from("/work/repo/app/node_modules/.bin"),
+            PathBuf::from("/work/repo/node_modules/.bin"),
+            PathBuf::from("/work/node_modules/.bin"),
+            PathBuf::from("/node_modules/.bin"),
+            PathBuf::from("/usr/bin"),
+            PathBuf::from("/bin"),
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_script_env() {
+        let env = scripts::script_env(Path::new("/work/app"), &json!({ "name": "app" }), "test", "jest").unwrap();
+        let get = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+
+        assert_eq!(get("npm_package_name"), Some(OsString::from("app")));
+        assert_eq!(get("npm_lifecycle_event"), Some(OsString::from("test")));
+        assert_eq!(get("npm_lifecycle_script"), Some(OsString::from("jest")));
+        assert!(get("INIT_CWD").is_some());
+        assert_eq!(std::env::split_paths(&get("PATH").unwrap()).next(), Some(PathBuf::from("/work/app/node_modules/.bin")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_exit_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_json = json!({ "name": "app" });
+        let run = |script: &'static str| {
+            let command = scripts::command(dir.path(), &package_json, "test", script).unwrap();
+            scripts::run(command)
+        };
+
+        assert_eq!(run("true").await.unwrap(), 0);
+        assert_eq!(run("exit 7").await.unwrap(), 7);
+        // Killed by SIGTERM, reported like a shell does
+        assert_eq!(run("kill -TERM $$").await.unwrap(), 143);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_root_scripts_run_in_lifecycle_order() {
+        let dir = tempfile::tempdir().unwrap();
+        // Declared out of order, plus scripts that are not lifecycle stages
+        let stages = ["prepare", "postinstall", "test", "install", "prepublish", "preinstall", "build"];
+        let scripts: serde_json::Map<String, serde_json::Value> = stages.iter()
+            .map(|stage| (stage.to_string(), json!(format!("echo {} >> order.txt", stage))))
+            .collect();
+        std::fs::write(dir.path().join("package.json"), json!({ "name": "app", "scripts": scripts }).to_string()).unwrap();
+        let order = || std::fs::read_to_string(dir.path().join("order.txt")).unwrap_or_default();
+
+        scripts::run_root_scripts(dir.path(), scripts::ROOT_STAGES_BEFORE_INSTALL, true).await.unwrap();
+        scripts::run_root_scripts(dir.path(), scripts::ROOT_STAGES_AFTER_INSTALL, true).await.unwrap();
+        assert_eq!(order(), "");
+
+        scripts::run_root_scripts(dir.path(), scripts::ROOT_STAGES_BEFORE_INSTALL, false).await.unwrap();
+        assert_eq!(order(), "preinstall\n");
+        scripts::run_root_scripts(dir.path(), scripts::ROOT_STAGES_AFTER_INSTALL, false).await.unwrap();
+        assert_eq!(order(), "preinstall\ninstall\npostinstall\nprepublish\nprepare\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_with_args() {
+        let args: Vec<String> = ["--watch", "it's", "a b", "$HOME", ""].iter().map(|s| s.to_string()).collect();
+        let command = scripts::with_args("printf '%s|'", &args);
+        assert_eq!(command, "printf '%s|' --watch 'it'\\''s' 'a b' '$HOME' ''");
+
+        // The shell hands every argument over unchanged
+        let output = std::process::Command::new("sh").arg("-c").arg(&command).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "--watch|it's|a b|$HOME||");
+    }
+
+    #[test]
+    fn test_install_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_json = json!({ "scripts": { "postinstall": "node install.js", "test": "jest", "preinstall": "echo hi" } });
+        let stages: Vec<_> = scripts::install_scripts(dir.path(), &package_json).into_iter().map(|(stage, _)| stage).collect();
+        assert_eq!(stages, vec!["preinstall", "postinstall"]);
+
+        // Native addons without an install script are built with node-gyp
+        std::fs::write(dir.path().join("binding.gyp"), "{}").unwrap();
+        let package_json = json!({ "scripts": { "postinstall": "node done.js" } });
+        assert_eq!(scripts::install_scripts(dir.path(), &package_json), vec![
+            ("install".to_string(), "node-gyp rebuild".to_string()),
+            ("postinstall".to_string(), "node done.js".to_string()),
+        ]);
+        assert!(scripts::install_scripts(dir.path(), &json!({ "scripts": { "install": "make" } }))
+            .iter().all(|(_, script)| script == "make"));
+    }
+}
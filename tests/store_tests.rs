@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod tests {
+    use crate::integrity::Integrity;
+    use crate::store::{self, StoredPackage};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    // A package in a fake store with a single index.js
+    fn stored(store: &Path, name: &str, version: &str, deps: &[(&str, &str)]) -> StoredPackage {
+        let files = store.join(format!("{}-{}", name.replace('/', "+"), version));
+        fs::create_dir_all(&files).unwrap();
+        fs::write(files.join("index.js"), format!("// {}@{}", name, version)).unwrap();
+        StoredPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            files,
+            dependencies: deps.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    fn top_level(roots: &[(&str, &str)]) -> BTreeMap<String, String> {
+        roots.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_content_key() {
+        let integrity = Integrity::of(b"hello");
+        let key = integrity.content_key();
+        assert!(key.starts_with("sha512-"));
+        assert_eq!(key.len(), "sha512-".len() + 128);
+        assert_eq!(key, Integrity::of(b"hello").content_key());
+        assert_ne!(key, Integrity::of(b"world").content_key());
+
+        // The strongest hash names the entry, so a sha1 shasum alone gives a different key
+        let sha1 = Integrity::from_shasum("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d").unwrap();
+        assert_eq!(sha1.content_key(), "sha1-aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_isolated_layout() {
+        use std::os::unix::fs::MetadataExt;
+
+        let store = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let packages = vec![
+            stored(store.path(), "app-lib", "1.0.0", &[("@scope/util", "2.0.0")]),
+            stored(store.path(), "@scope/util", "2.0.0", &[("ms", "2.1.3")]),
+            stored(store.path(), "ms", "2.1.3", &[]),
+        ];
+
+        // Left over from a hoisted install
+        fs::create_dir_all(project.path().join("node_modules/ms")).unwrap();
+
+        store::link(project.path(), &packages, &top_level(&[("app-lib", "1.0.0")])).unwrap();
+        let node_modules = project.path().join("node_modules");
+
+        // Files are hardlinks into the store
+        let linked = node_modules.join(".a/app-lib@1.0.0/node_modules/app-lib/index.js");
+        assert_eq!(fs::metadata(&linked).unwrap().ino(),
+                   fs::metadata(packages[0].files.join("index.js")).unwrap().ino());
+
+        // Dependencies resolve through symlinks, scoped ones included
+        assert_eq!(fs::read_to_string(node_modules.join("app-lib/index.js")).unwrap(), "// app-lib@1.0.0");
+        assert_eq!(fs::read_to_string(node_modules.join(".a/app-lib@1.0.0/node_modules/@scope/util/index.js")).unwrap(),
+                   "// @scope/util@2.0.0");
+        assert_eq!(fs::read_to_string(node_modules.join(".a/@scope+util@2.0.0/node_modules/ms/index.js")).unwrap(),
+                   "// ms@2.1.3");
+
+        // Only declared dependencies are visible
+        assert!(fs::symlink_metadata(node_modules.join("ms")).is_err());
+        assert!(fs::symlink_metadata(node_modules.join("@scope/util")).is_err());
+        assert!(fs::symlink_metadata(node_modules.join(".a/app-lib@1.0.0/node_modules/ms")).is_err());
+
+        // Linking again is a no-op
+        store::link(project.path(), &packages, &top_level(&[("app-lib", "1.0.0")])).unwrap();
+        assert!(node_modules.join("app-lib/index.js").exists());
+    }
+}